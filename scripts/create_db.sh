@@ -6,24 +6,88 @@ DATABASE_URL="database.db"
 sqlite3 $DATABASE_URL <<EOF
 CREATE TABLE IF NOT EXISTS users (
     id INTEGER PRIMARY KEY,
-    username TEXT NOT NULL,
+    username TEXT,
     first_name TEXT NOT NULL,
-    last_name TEXT NOT NULL
+    last_name TEXT,
+    phone TEXT,
+    cart_reminders BOOLEAN NOT NULL DEFAULT TRUE,
+    -- 'active', 'restricted' (can browse but not order) or 'banned' (ignored by the bot).
+    status TEXT NOT NULL DEFAULT 'active',
+    last_seen TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every change of a user's status, with the admin who made it.
+CREATE TABLE IF NOT EXISTS user_status_changes (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    reason TEXT,
+    changed_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id)
 );
 
 CREATE TABLE IF NOT EXISTS products (
     id INTEGER PRIMARY KEY,
     name TEXT NOT NULL,
-    image TEXT NOT NULL,
     price INTEGER NOT NULL,
-    description TEXT NOT NULL
+    description TEXT NOT NULL,
+    category TEXT,
+    max_quantity INTEGER,
+    in_stock BOOLEAN NOT NULL DEFAULT TRUE,
+    visible BOOLEAN NOT NULL DEFAULT TRUE,
+    archived BOOLEAN NOT NULL DEFAULT FALSE
 );
 
-CREATE TABLE IF NOT EXISTS carts (
+-- Replaces products.image. Upgrade an older database with migrate_db.sh.
+CREATE TABLE IF NOT EXISTS product_images (
+    id INTEGER PRIMARY KEY,
+    product_id INTEGER NOT NULL,
+    file_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    FOREIGN KEY (product_id) REFERENCES products (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS coupons (
+    id INTEGER PRIMARY KEY,
+    code TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    min_order INTEGER NOT NULL DEFAULT 0,
+    expires_on DATE,
+    max_uses INTEGER,
+    max_uses_per_user INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS addresses (
     id INTEGER PRIMARY KEY,
     user_id INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    address TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id)
+);
+
+-- A cart belongs to a user, or to a group chat whose members share it.
+CREATE TABLE IF NOT EXISTS carts (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER,
+    chat_id INTEGER,
+    coupon_id INTEGER,
+    fulfillment_method TEXT,
+    address_id INTEGER,
+    -- The location shared to check the cart against the delivery zones.
+    latitude REAL,
+    longitude REAL,
+    use_points BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    reminded_at TIMESTAMP,
     FOREIGN KEY (user_id) REFERENCES users (id),
-    UNIQUE (user_id)
+    FOREIGN KEY (coupon_id) REFERENCES coupons (id) ON DELETE SET NULL,
+    FOREIGN KEY (address_id) REFERENCES addresses (id) ON DELETE SET NULL,
+    UNIQUE (user_id),
+    UNIQUE (chat_id)
 );
 
 CREATE TABLE IF NOT EXISTS cart_items (
@@ -31,17 +95,64 @@ CREATE TABLE IF NOT EXISTS cart_items (
     cart_id INTEGER NOT NULL,
     product_id INTEGER NOT NULL,
     quantity INTEGER NOT NULL,
+    added_by INTEGER NOT NULL,
+    -- The unit price the cart last showed, checked against the catalog at checkout.
+    price INTEGER NOT NULL,
     FOREIGN KEY (cart_id) REFERENCES carts (id),
+    FOREIGN KEY (added_by) REFERENCES users (id),
     FOREIGN KEY (product_id) REFERENCES products (id) ON DELETE CASCADE
 );
 
+-- The cart message shown in each chat, edited in place whenever the cart changes.
+CREATE TABLE IF NOT EXISTS cart_messages (
+    chat_id INTEGER PRIMARY KEY,
+    message_id INTEGER NOT NULL
+);
+
+-- Any change to a cart's items counts as activity for reminders and expiry. Refreshing the
+-- prices a cart shows does not.
+CREATE TRIGGER IF NOT EXISTS cart_items_inserted AFTER INSERT ON cart_items BEGIN
+    UPDATE carts SET updated_at = CURRENT_TIMESTAMP, reminded_at = NULL WHERE id = NEW.cart_id;
+END;
+
+-- Dropped first so databases that have the older trigger on every column get this one.
+DROP TRIGGER IF EXISTS cart_items_updated;
+CREATE TRIGGER cart_items_updated AFTER UPDATE OF quantity ON cart_items BEGIN
+    UPDATE carts SET updated_at = CURRENT_TIMESTAMP, reminded_at = NULL WHERE id = NEW.cart_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS cart_items_deleted AFTER DELETE ON cart_items BEGIN
+    UPDATE carts SET updated_at = CURRENT_TIMESTAMP, reminded_at = NULL WHERE id = OLD.cart_id;
+END;
+
 CREATE TABLE IF NOT EXISTS orders (
     id INTEGER PRIMARY KEY,
     user_id INTEGER NOT NULL,
-    FOREIGN KEY (user_id) REFERENCES users (id),
+    -- The group chat a shared cart was checked out from.
+    chat_id INTEGER,
+    currency TEXT NOT NULL DEFAULT 'USD',
+    subtotal INTEGER NOT NULL DEFAULT 0,
+    promotions INTEGER NOT NULL DEFAULT 0,
+    coupon_id INTEGER,
+    discount INTEGER NOT NULL DEFAULT 0,
+    tax INTEGER NOT NULL DEFAULT 0,
+    tax_included BOOLEAN NOT NULL DEFAULT FALSE,
+    fulfillment_method TEXT,
+    delivery_fee INTEGER NOT NULL DEFAULT 0,
+    total INTEGER NOT NULL DEFAULT 0,
+    -- Contact details copied at checkout for methods that require an address.
+    phone TEXT,
+    address TEXT,
+    delivery_zone TEXT,
+    points_discount INTEGER NOT NULL DEFAULT 0,
     fulfilled BOOLEAN DEFAULT FALSE,
+    cancelled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- When a shared order's bill was sent to the group members.
+    bill_split_at TIMESTAMP,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id),
+    FOREIGN KEY (coupon_id) REFERENCES coupons (id)
 );
 
 CREATE TABLE IF NOT EXISTS order_items (
@@ -49,8 +160,36 @@ CREATE TABLE IF NOT EXISTS order_items (
     order_id INTEGER NOT NULL,
     product_id INTEGER NOT NULL,
     quantity INTEGER NOT NULL,
+    price INTEGER NOT NULL,
+    added_by INTEGER,
     FOREIGN KEY (order_id) REFERENCES orders (id),
-    FOREIGN KEY (product_id) REFERENCES products (id) ON DELETE CASCADE
+    FOREIGN KEY (added_by) REFERENCES users (id),
+    FOREIGN KEY (product_id) REFERENCES products (id)
+);
+
+-- Every change of a customer's loyalty points. The balance is the sum of a customer's rows.
+CREATE TABLE IF NOT EXISTS loyalty_points (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    order_id INTEGER NOT NULL,
+    points INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id),
+    FOREIGN KEY (order_id) REFERENCES orders (id)
+);
+
+CREATE TABLE IF NOT EXISTS wishlist_items (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    product_id INTEGER NOT NULL,
+    -- Whether the product could be ordered and its price when the customer was last told.
+    available BOOLEAN NOT NULL,
+    price INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id),
+    FOREIGN KEY (product_id) REFERENCES products (id) ON DELETE CASCADE,
+    UNIQUE (user_id, product_id)
 );
 EOF
 
@@ -5,11 +5,18 @@ DATABASE_URL="database.db"
 # Drop tables if they exist
 sqlite3 $DATABASE_URL <<EOF
 DROP TABLE IF EXISTS users;
+DROP TABLE IF EXISTS user_status_changes;
+DROP TABLE IF EXISTS addresses;
 DROP TABLE IF EXISTS products;
+DROP TABLE IF EXISTS product_images;
+DROP TABLE IF EXISTS coupons;
 DROP TABLE IF EXISTS carts;
 DROP TABLE IF EXISTS cart_items;
+DROP TABLE IF EXISTS cart_messages;
 DROP TABLE IF EXISTS orders;
 DROP TABLE IF EXISTS order_items;
+DROP TABLE IF EXISTS loyalty_points;
+DROP TABLE IF EXISTS wishlist_items;
 EOF
 
 echo "Database tables dropped successfully."
\ No newline at end of file
@@ -0,0 +1,113 @@
+#!/bin/bash
+
+DATABASE_URL="database.db"
+
+# Brings a database created by an older create_db.sh up to its current schema. Back it up first.
+#
+# Every table whose definition changed is rebuilt and its rows are copied over, because ALTER
+# TABLE can't make a column nullable or add a NOT NULL column without a default. Missing columns
+# get their defaults, except for these, which are filled from related rows:
+# - cart_items.added_by: the cart's owner.
+# - cart_items.price and order_items.price: the product's current price.
+# - products.image: moved into product_images as the product's cover.
+
+# The schema create_db.sh creates, run inside the migration's transaction.
+SCHEMA=$(sed -n '/^sqlite3 .*<<EOF$/,/^EOF$/{//!p}' "$(dirname "$0")/create_db.sh")
+
+# Tables in the order they are copied, so the rows filled from other tables are already there.
+TABLES="users user_status_changes products product_images coupons addresses carts cart_items
+cart_messages orders order_items loyalty_points wishlist_items"
+
+FRESH=$(mktemp)
+trap 'rm -f "$FRESH"' EXIT
+sqlite3 "$FRESH" <<< "$SCHEMA"
+
+definition() {
+    sqlite3 "$1" "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = '$2';"
+}
+
+columns() {
+    sqlite3 "$1" "SELECT name FROM pragma_table_info('$2');"
+}
+
+# The cart_items triggers would move to a renamed cart_items, and copying the items would mark
+# every cart as just updated. The schema creates them again after the copy.
+TRIGGERS="DROP TRIGGER IF EXISTS cart_items_inserted;
+DROP TRIGGER IF EXISTS cart_items_updated;
+DROP TRIGGER IF EXISTS cart_items_deleted;"
+RENAME=""
+COPY=""
+DROP=""
+
+for table in $TABLES; do
+    current=$(definition $DATABASE_URL $table)
+
+    if [ -z "$current" ] || [ "$current" == "$(definition "$FRESH" $table)" ]; then
+        continue
+    fi
+
+    echo "Rebuilding $table."
+
+    RENAME+=$'\n'"ALTER TABLE $table RENAME TO ${table}_old;"
+    DROP+=$'\n'"DROP TABLE ${table}_old;"
+
+    old_columns=$(columns $DATABASE_URL $table)
+    names=()
+    values=()
+
+    for column in $(columns "$FRESH" $table); do
+        if grep -qx "$column" <<< "$old_columns"; then
+            names+=("$column")
+            values+=("$column")
+            continue
+        fi
+
+        case "$table.$column" in
+            cart_items.added_by)
+                value="(SELECT user_id FROM carts WHERE carts.id = cart_id)" ;;
+            cart_items.price | order_items.price)
+                value="(SELECT price FROM products WHERE products.id = product_id)" ;;
+            *)
+                continue ;;
+        esac
+
+        names+=("$column")
+        values+=("$value")
+    done
+
+    COPY+=$'\n'"INSERT INTO $table ($(IFS=,; echo "${names[*]}"))
+    SELECT $(IFS=,; echo "${values[*]}") FROM ${table}_old;"
+
+    if [ "$table" == "products" ] && grep -qx "image" <<< "$old_columns"; then
+        COPY+=$'\n'"INSERT INTO product_images (product_id, file_id, position)
+    SELECT id, image, 0 FROM products_old
+    WHERE image != '' AND NOT EXISTS (SELECT 1 FROM product_images WHERE product_id = products_old.id);"
+    fi
+done
+
+if [ -z "$RENAME" ]; then
+    echo "Database is already up to date."
+    exit 0
+fi
+
+# The old tables are renamed without rewriting the foreign keys that point at them, so those keep
+# referring to the rebuilt tables.
+if ! sqlite3 -bail $DATABASE_URL <<EOF
+PRAGMA foreign_keys = OFF;
+PRAGMA legacy_alter_table = ON;
+BEGIN;
+$TRIGGERS
+$RENAME
+$SCHEMA
+$TRIGGERS
+$COPY
+$DROP
+$SCHEMA
+COMMIT;
+EOF
+then
+    echo "Migration failed. The database was left unchanged."
+    exit 1
+fi
+
+echo "Database migrated successfully."
\ No newline at end of file
@@ -4,8 +4,11 @@ DATABASE_URL="database.db"
 
 # Seed database
 sqlite3 $DATABASE_URL <<EOF
-INSERT OR IGNORE INTO products (name, image, price, description) VALUES
-    ('Runtz', 'https://images.leafly.com/flower-images/runtz-nug-image.jpg', 1000, '')
+INSERT OR IGNORE INTO products (id, name, price, description) VALUES
+    (1, 'Runtz', 1000, '');
+
+INSERT OR IGNORE INTO product_images (id, product_id, file_id, position) VALUES
+    (1, 1, 'https://images.leafly.com/flower-images/runtz-nug-image.jpg', 0);
 EOF
 
 echo "Database seeded successfully."
\ No newline at end of file
@@ -0,0 +1,240 @@
+use format as f;
+
+use crate::money::Money;
+
+/// Columns of the catalog CSV, shared by import and export.
+pub const HEADER: [&str; 7] = [
+    "id",
+    "name",
+    "description",
+    "price",
+    "category",
+    "images",
+    "visible",
+];
+
+/// Separates image `file_id`s or URLs inside the `images` column.
+const IMAGE_SEPARATOR: char = '|';
+
+/// A product as it appears in the catalog CSV. Rows without an id create new products.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogRow {
+    pub id: Option<i64>,
+    pub name: String,
+    pub description: String,
+    pub price: Money,
+    pub category: Option<String>,
+    pub images: Vec<String>,
+    pub visible: bool,
+}
+
+/// A validation problem found on a given CSV record (the header is row 1).
+#[derive(Debug, PartialEq)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+/// Parses and validates a whole catalog, returning every row error at once.
+pub fn parse_catalog(data: &[u8]) -> Result<Vec<CatalogRow>, Vec<RowError>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+
+    let header = match reader.headers() {
+        Ok(header) => header.iter().map(str::trim).collect::<Vec<_>>(),
+        Err(err) => {
+            return Err(vec![RowError {
+                row: 1,
+                message: err.to_string(),
+            }])
+        }
+    };
+
+    if header != HEADER {
+        return Err(vec![RowError {
+            row: 1,
+            message: f!("expected the header {}", HEADER.join(",")),
+        }]);
+    }
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+
+        match record
+            .map_err(|err| err.to_string())
+            .and_then(|record| parse_row(&record))
+        {
+            Ok(row) => rows.push(row),
+            Err(message) => errors.push(RowError { row, message }),
+        }
+    }
+
+    let mut ids = rows.iter().filter_map(|row| row.id).collect::<Vec<_>>();
+    ids.sort_unstable();
+    if let Some(id) = ids
+        .windows(2)
+        .find(|ids| ids[0] == ids[1])
+        .map(|ids| ids[0])
+    {
+        errors.push(RowError {
+            row: 1,
+            message: f!("product id {id} appears more than once"),
+        });
+    }
+
+    if rows.is_empty() && errors.is_empty() {
+        errors.push(RowError {
+            row: 1,
+            message: "the catalog has no products".to_owned(),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(rows)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_row(record: &csv::StringRecord) -> Result<CatalogRow, String> {
+    if record.len() != HEADER.len() {
+        return Err(f!(
+            "expected {} columns, found {}",
+            HEADER.len(),
+            record.len()
+        ));
+    }
+
+    let column = |index: usize| record.get(index).unwrap_or_default().trim();
+
+    let id = match column(0) {
+        "" => None,
+        id => Some(
+            id.parse::<i64>()
+                .map_err(|_| f!("invalid product id {id:?}"))?,
+        ),
+    };
+
+    let name = column(1).to_owned();
+    if name.is_empty() {
+        return Err("name is required".to_owned());
+    }
+
+    let price = column(3)
+        .parse::<Money>()
+        .map_err(|err| f!("invalid price {:?}: {err}", column(3)))?;
+
+    let category = Some(column(4))
+        .filter(|category| !category.is_empty())
+        .map(ToOwned::to_owned);
+
+    let images = column(5)
+        .split(IMAGE_SEPARATOR)
+        .map(str::trim)
+        .filter(|image| !image.is_empty())
+        .map(ToOwned::to_owned)
+        .collect();
+
+    let visible = match column(6).to_lowercase().as_str() {
+        "" | "true" | "yes" | "1" => true,
+        "false" | "no" | "0" => false,
+        visible => return Err(f!("invalid visible value {visible:?}")),
+    };
+
+    Ok(CatalogRow {
+        id,
+        name,
+        description: column(2).to_owned(),
+        price,
+        category,
+        images,
+        visible,
+    })
+}
+
+/// Writes the catalog in the same format `parse_catalog` reads.
+pub fn write_catalog(rows: &[CatalogRow]) -> eyre::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer.write_record(HEADER)?;
+
+    for row in rows {
+        writer.write_record([
+            row.id.map(|id| id.to_string()).unwrap_or_default(),
+            row.name.clone(),
+            row.description.clone(),
+            row.price.to_decimal_string(),
+            row.category.clone().unwrap_or_default(),
+            row.images.join(&IMAGE_SEPARATOR.to_string()),
+            row.visible.to_string(),
+        ])?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_catalog() {
+        let data = "id,name,description,price,category,images,visible\n\
+            1,Runtz,\"Sweet, fruity\",10.50,flower,a|b,true\n\
+            ,New,,5,,,no\n";
+
+        let rows = parse_catalog(data.as_bytes()).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].id, Some(1));
+        assert_eq!(rows[0].price, Money::from_cents(1050));
+        assert_eq!(rows[0].description, "Sweet, fruity");
+        assert_eq!(rows[0].category.as_deref(), Some("flower"));
+        assert_eq!(rows[0].images, vec!["a", "b"]);
+        assert_eq!(rows[1].id, None);
+        assert_eq!(rows[1].category, None);
+        assert!(rows[1].images.is_empty());
+        assert!(!rows[1].visible);
+    }
+
+    #[test]
+    fn test_parse_catalog_reports_every_row() {
+        let data = "id,name,description,price,category,images,visible\n\
+            x,Runtz,,10.50,,,true\n\
+            ,,,5.00,,,true\n\
+            ,Ok,,-1,,,true\n";
+
+        let errors = parse_catalog(data.as_bytes()).unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(|error| error.row).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_parse_catalog_rejects_bad_header() {
+        let errors = parse_catalog(b"name,price\nRuntz,1000\n").unwrap_err();
+
+        assert_eq!(errors[0].row, 1);
+    }
+
+    #[test]
+    fn test_write_catalog_round_trip() {
+        let rows = vec![CatalogRow {
+            id: Some(3),
+            name: "Runtz".to_owned(),
+            description: "Line one\nline two".to_owned(),
+            price: Money::from_cents(1050),
+            category: Some("flower".to_owned()),
+            images: vec!["file-a".to_owned(), "https://example.com/b.jpg".to_owned()],
+            visible: false,
+        }];
+
+        let data = write_catalog(&rows).unwrap();
+
+        assert_eq!(parse_catalog(&data).unwrap(), rows);
+    }
+}
@@ -0,0 +1,110 @@
+use format as f;
+
+use crate::money::{Currency, Money};
+
+/// A cart item as the customer last saw it, next to the product's current data.
+#[derive(Debug, Clone)]
+pub struct ItemSnapshot {
+    pub name: String,
+    pub seen_price: Money,
+    pub price: Money,
+    pub available: bool,
+}
+
+/// Something about a cart item that changed since the customer last saw the cart.
+#[derive(Debug, PartialEq)]
+pub enum CartChange {
+    Unavailable {
+        name: String,
+    },
+    PriceChanged {
+        name: String,
+        from: Money,
+        to: Money,
+    },
+}
+
+impl ItemSnapshot {
+    pub fn change(&self) -> Option<CartChange> {
+        if !self.available {
+            return Some(CartChange::Unavailable {
+                name: self.name.clone(),
+            });
+        }
+
+        (self.price != self.seen_price).then(|| CartChange::PriceChanged {
+            name: self.name.clone(),
+            from: self.seen_price,
+            to: self.price,
+        })
+    }
+}
+
+impl CartChange {
+    pub fn describe(&self, currency: &Currency) -> String {
+        match self {
+            CartChange::Unavailable { name } => {
+                f!("{name} is no longer available and was removed.")
+            }
+            CartChange::PriceChanged { name, from, to } => f!(
+                "{name} {} from {} to {}.",
+                if to > from { "went up" } else { "went down" },
+                from.format(currency),
+                to.format(currency)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(seen_price: i64, price: i64, available: bool) -> ItemSnapshot {
+        ItemSnapshot {
+            name: "Runtz".to_owned(),
+            seen_price: Money::from_cents(seen_price),
+            price: Money::from_cents(price),
+            available,
+        }
+    }
+
+    #[test]
+    fn test_change() {
+        assert_eq!(snapshot(1000, 1000, true).change(), None);
+        assert_eq!(
+            snapshot(1000, 1200, true).change(),
+            Some(CartChange::PriceChanged {
+                name: "Runtz".to_owned(),
+                from: Money::from_cents(1000),
+                to: Money::from_cents(1200),
+            })
+        );
+        assert_eq!(
+            snapshot(1000, 1200, false).change(),
+            Some(CartChange::Unavailable {
+                name: "Runtz".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        let currency = Currency::default();
+
+        assert_eq!(
+            snapshot(1000, 800, true)
+                .change()
+                .unwrap()
+                .describe(&currency),
+            "Runtz went down from $10.00 to $8.00."
+        );
+        assert_eq!(
+            snapshot(1000, 1000, false)
+                .change()
+                .unwrap()
+                .describe(&currency),
+            "Runtz is no longer available and was removed."
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
+    money::Money,
     schema::{AppDialogue, HandlerResult},
-    utils::assert_admin_id,
+    utils::{assert_admin_id, upload_image_url, Config},
     State,
 };
 use format as f;
@@ -60,9 +61,12 @@ pub async fn receive_product_description(
                 })
                 .await?;
 
-            bot.send_message(msg.chat.id, "Please, send me the product price in cents.")
-                .reply_markup(ForceReply::default())
-                .await?;
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me the product price, e.g. 12.50.",
+            )
+            .reply_markup(ForceReply::default())
+            .await?;
         }
         None => {
             bot.send_message(msg.chat.id, "Please, send me the product description.")
@@ -79,10 +83,11 @@ pub async fn receive_product_price(
     dialogue: AppDialogue,
     (product_name, product_description): (String, String),
     msg: Message,
+    config: Config,
 ) -> HandlerResult {
     match msg.text().map(ToOwned::to_owned) {
         Some(product_price) => {
-            let product_price = match product_price.parse::<i64>() {
+            let product_price = match Money::parse(&product_price, &config.currency) {
                 Ok(price) => price,
                 Err(_) => {
                     bot.send_message(msg.chat.id, "Invalid price. Try again.")
@@ -99,9 +104,12 @@ pub async fn receive_product_price(
                 })
                 .await?;
 
-            bot.send_message(msg.chat.id, "Please, send me the product image.")
-                .reply_markup(ForceReply::default())
-                .await?;
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me the product image as a photo or an image URL.",
+            )
+            .reply_markup(ForceReply::default())
+            .await?;
         }
         None => {
             bot.send_message(msg.chat.id, "Please, send me the product price.")
@@ -115,18 +123,32 @@ pub async fn receive_product_price(
 
 pub async fn receive_product_image(
     bot: Bot,
-    (product_name, product_description, product_price): (String, String, i64),
+    (product_name, product_description, product_price): (String, String, Money),
     msg: Message,
     dialogue: AppDialogue,
     pool: SqlitePool,
 ) -> HandlerResult {
-    match msg.text().map(ToOwned::to_owned) {
+    let product_image = match (msg.photo(), msg.text()) {
+        (Some(photos), _) => photos.last().map(|photo| photo.file.id.clone()),
+        (None, Some(url)) => upload_image_url(&bot, msg.chat.id, url).await,
+        (None, None) => None,
+    };
+
+    match product_image {
         Some(product_image) => {
-            sqlx::query!(
-                "INSERT INTO products (name, description, price, image) VALUES (?, ?, ?, ?)",
+            let product_price = product_price.cents();
+            let product = sqlx::query!(
+                "INSERT INTO products (name, description, price) VALUES (?, ?, ?) RETURNING id",
                 product_name,
                 product_description,
-                product_price,
+                product_price
+            )
+            .fetch_one(&pool)
+            .await?;
+
+            sqlx::query!(
+                "INSERT INTO product_images (product_id, file_id, position) VALUES (?, ?, 0)",
+                product.id,
                 product_image
             )
             .execute(&pool)
@@ -134,15 +156,18 @@ pub async fn receive_product_image(
 
             bot.send_message(
                 msg.chat.id,
-                f!("Product {product_name} added successfully."),
+                f!("Product {product_name} added successfully. Use /gallery to add more images."),
             )
             .await?;
 
             dialogue.exit().await?;
         }
         None => {
-            bot.send_message(msg.chat.id, "Please, send me the product image.")
-                .await?;
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me the product image as a photo or an image URL.",
+            )
+            .await?;
         }
     }
 
@@ -1,11 +1,12 @@
 use teloxide::prelude::*;
 
 use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::delete_command;
 
 pub async fn cancel(bot: Bot, dialogue: AppDialogue, msg: Message) -> HandlerResult {
     tracing::info!("processing /cancel command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
     bot.send_message(msg.chat.id, "Cancelled the dialogue.")
         .await?;
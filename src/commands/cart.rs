@@ -3,288 +3,1587 @@ use sqlx::SqlitePool;
 use teloxide::{
     dispatching::dialogue::GetChatId,
     prelude::*,
-    types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup},
+    types::{
+        ButtonRequest, ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton,
+        KeyboardMarkup, KeyboardRemove, MessageId,
+    },
+    ApiError, RequestError,
 };
 
 use crate::{
+    checkout::{CartChange, ItemSnapshot},
+    commands::{
+        coupons::{fetch_cart_coupon, fetch_coupon_uses, CartCoupon},
+        moderation::{is_restricted, RESTRICTED},
+        orders::{notify_staff, send_order_detail},
+        points::{fetch_points_balance, record_points},
+    },
+    coupons::{Coupon, CouponError},
+    geo::{DeliveryZone, Location},
+    limits::{check_cart, check_quantity, ItemQuantity, QuantityError},
+    loyalty::PointsReason,
+    money::{Currency, Money},
+    pricing::{summarize, Line, Summary},
+    promotions::{apply_promotions, Clock},
     schema::{AppDialogue, HandlerResult},
-    utils::format_price,
+    utils::{delete_command, Config},
     State,
 };
 
-pub async fn view_cart(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+/// A customer's own cart, or the cart shared by the members of a group chat.
+#[derive(Clone)]
+pub struct Cart {
+    pub id: i64,
+    pub coupon_id: Option<i64>,
+    pub fulfillment_method: Option<String>,
+    pub address_id: Option<i64>,
+    /// The location shared to check the cart against the delivery zones.
+    pub location: Option<Location>,
+    /// Whether the customer's loyalty points are redeemed at checkout.
+    pub use_points: bool,
+    pub shared: bool,
+}
+
+/// The delivery address chosen for a cart, with the phone number of the customer it belongs to.
+struct DeliveryAddress {
+    /// The member whose profile holds the address.
+    user_id: i64,
+    label: String,
+    address: String,
+    phone: Option<String>,
+}
+
+/// A cart item as added by one customer. Shared carts can hold the same product once per member.
+pub struct CartItem {
+    pub id: i64,
+    pub product_id: i64,
+    pub name: String,
+    pub unit_price: Money,
+    pub quantity: i64,
+    pub added_by: i64,
+    /// The first name of the customer who added the item.
+    pub added_by_name: String,
+}
+
+impl CartItem {
+    fn gross(&self) -> Money {
+        self.unit_price
+            .checked_mul(self.quantity)
+            .unwrap_or_default()
+    }
+}
+
+/// A cart priced with the configured promotions, its coupon and tax.
+pub struct PricedCart {
+    pub items: Vec<CartItem>,
+    /// The priced lines, one per product.
+    pub lines: Vec<Line>,
+    pub coupon: Option<CartCoupon>,
+    pub fulfillment_method: Option<String>,
+    /// The method's minimum order, if the cart does not reach it yet.
+    pub minimum_order: Option<Money>,
+    /// Where the cart is delivered, if its method only delivers within the delivery zones.
+    pub delivery_zone: Option<ZoneStatus>,
+    /// The loyalty points redeemed on the cart, out of the customer's balance.
+    pub points: i64,
+    pub points_balance: i64,
+    pub summary: Summary,
+}
+
+/// Where a cart's shared location falls among the delivery zones.
+pub enum ZoneStatus {
+    NoLocation,
+    OutOfZone,
+    InZone(DeliveryZone),
+}
+
+pub async fn view_cart(bot: Bot, msg: Message, pool: SqlitePool, config: Config) -> HandlerResult {
     tracing::info!("processing /cart command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
     let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
 
-    let cart = sqlx::query!("SELECT * FROM carts WHERE user_id = ?", id)
-        .fetch_one(&pool)
+    send_cart(&bot, msg.chat.id, &pool, &config, id).await
+}
+
+/// Opens the cart from the button on an abandoned cart reminder.
+pub async fn view_cart_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    if let Some(chat_id) = q.chat_id() {
+        send_cart(&bot, chat_id, &pool, &config, user_id).await?;
+    }
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Sends the cart as a new message, replacing the chat's previous cart message.
+async fn send_cart(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    config: &Config,
+    user_id: i64,
+) -> HandlerResult {
+    let (text, keyboard) = cart_content(pool, config, chat_id, user_id).await?;
+
+    replace_cart_message(bot, pool, chat_id, text, keyboard).await
+}
+
+/// Edits the chat's cart message in place after the cart changed. A message that can no longer
+/// be edited, e.g. because it is too old, is replaced with a new one.
+pub async fn update_cart_message(
+    bot: &Bot,
+    pool: &SqlitePool,
+    config: &Config,
+    chat_id: ChatId,
+    user_id: i64,
+) -> HandlerResult {
+    let message_id = match tracked_cart_message(pool, chat_id).await? {
+        Some(message_id) => message_id,
+        None => return Ok(()),
+    };
+
+    let (text, keyboard) = cart_content(pool, config, chat_id, user_id).await?;
+
+    match bot
+        .edit_message_text(chat_id, message_id, &text)
+        .reply_markup(keyboard.clone())
+        .await
+    {
+        // Choosing the fulfillment method that is already selected leaves the cart unchanged.
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        Err(err) => {
+            tracing::debug!("replacing cart message in chat {}: {}", chat_id, err);
+            replace_cart_message(bot, pool, chat_id, text, keyboard).await
+        }
+    }
+}
+
+async fn replace_cart_message(
+    bot: &Bot,
+    pool: &SqlitePool,
+    chat_id: ChatId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+) -> HandlerResult {
+    clear_cart_message(bot, pool, chat_id).await?;
+
+    let message = bot
+        .send_message(chat_id, text)
+        .reply_markup(keyboard)
         .await?;
 
-    let cart_items_with_products = sqlx::query!(
-        "SELECT cart_items.*, products.name, products.price FROM cart_items
-        INNER JOIN products ON cart_items.product_id = products.id
-        WHERE cart_items.cart_id = ?",
+    track_cart_message(pool, chat_id, message.id).await?;
+
+    Ok(())
+}
+
+/// Deletes the chat's cart message, if it can still be deleted, and stops tracking it.
+async fn clear_cart_message(bot: &Bot, pool: &SqlitePool, chat_id: ChatId) -> HandlerResult {
+    if let Some(message_id) = tracked_cart_message(pool, chat_id).await? {
+        if let Err(err) = bot.delete_message(chat_id, message_id).await {
+            tracing::debug!("failed to delete cart message in chat {}: {}", chat_id, err);
+        }
+    }
+
+    sqlx::query!("DELETE FROM cart_messages WHERE chat_id = ?", chat_id.0)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn tracked_cart_message(
+    pool: &SqlitePool,
+    chat_id: ChatId,
+) -> Result<Option<MessageId>, sqlx::Error> {
+    let message = sqlx::query!(
+        "SELECT message_id FROM cart_messages WHERE chat_id = ?",
+        chat_id.0
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(message.map(|message| MessageId(message.message_id as i32)))
+}
+
+async fn track_cart_message(
+    pool: &SqlitePool,
+    chat_id: ChatId,
+    message_id: MessageId,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO cart_messages (chat_id, message_id) VALUES (?, ?)
+        ON CONFLICT (chat_id) DO UPDATE SET message_id = excluded.message_id",
+        chat_id.0,
+        message_id.0
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The cart message's text and keyboard, which has no buttons once the cart is empty.
+async fn cart_content(
+    pool: &SqlitePool,
+    config: &Config,
+    chat_id: ChatId,
+    user_id: i64,
+) -> Result<(String, InlineKeyboardMarkup), Box<dyn std::error::Error + Send + Sync>> {
+    let cart = fetch_cart(pool, Some(chat_id), user_id).await?;
+
+    Ok(render_cart(pool, config, &cart, user_id)
+        .await?
+        .unwrap_or_else(|| {
+            (
+                empty_cart_text(&cart).to_owned(),
+                InlineKeyboardMarkup::default(),
+            )
+        }))
+}
+
+/// Loads the cart `user_id` shops with in `chat_id`: the chat's shared cart in groups and the
+/// user's own cart anywhere else. The cart is created if it does not exist yet.
+pub async fn fetch_cart(
+    pool: &SqlitePool,
+    chat_id: Option<ChatId>,
+    user_id: i64,
+) -> Result<Cart, sqlx::Error> {
+    match chat_id.filter(|chat_id| !chat_id.is_user()) {
+        Some(ChatId(chat_id)) => {
+            sqlx::query!("INSERT OR IGNORE INTO carts (chat_id) VALUES (?)", chat_id)
+                .execute(pool)
+                .await?;
+
+            let cart = sqlx::query!(
+                r#"SELECT id AS "id!", coupon_id, fulfillment_method, address_id, latitude, longitude,
+                    use_points
+                FROM carts WHERE chat_id = ?"#,
+                chat_id
+            )
+            .fetch_one(pool)
+            .await?;
+
+            Ok(Cart {
+                id: cart.id,
+                coupon_id: cart.coupon_id,
+                fulfillment_method: cart.fulfillment_method,
+                address_id: cart.address_id,
+                location: cart_location(cart.latitude, cart.longitude),
+                use_points: cart.use_points,
+                shared: true,
+            })
+        }
+        None => {
+            sqlx::query!("INSERT OR IGNORE INTO carts (user_id) VALUES (?)", user_id)
+                .execute(pool)
+                .await?;
+
+            let cart = sqlx::query!(
+                r#"SELECT id AS "id!", coupon_id, fulfillment_method, address_id, latitude, longitude,
+                    use_points
+                FROM carts WHERE user_id = ?"#,
+                user_id
+            )
+            .fetch_one(pool)
+            .await?;
+
+            Ok(Cart {
+                id: cart.id,
+                coupon_id: cart.coupon_id,
+                fulfillment_method: cart.fulfillment_method,
+                address_id: cart.address_id,
+                location: cart_location(cart.latitude, cart.longitude),
+                use_points: cart.use_points,
+                shared: false,
+            })
+        }
+    }
+}
+
+fn cart_location(latitude: Option<f64>, longitude: Option<f64>) -> Option<Location> {
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => Some(Location {
+            latitude,
+            longitude,
+        }),
+        _ => None,
+    }
+}
+
+fn empty_cart_text(cart: &Cart) -> &'static str {
+    if cart.shared {
+        "The group cart is empty."
+    } else {
+        "Your cart is empty."
+    }
+}
+
+/// Re-renders the cart message a callback came from after the cart changed. That message becomes
+/// the chat's cart message, replacing any other.
+pub async fn refresh_cart_message(
+    bot: &Bot,
+    q: &CallbackQuery,
+    pool: &SqlitePool,
+    config: &Config,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    let (chat_id, message_id) = match (q.chat_id(), &q.message) {
+        (Some(chat_id), Some(message)) => (chat_id, message.id),
+        _ => return Ok(()),
+    };
+
+    adopt_cart_message(bot, pool, chat_id, message_id).await?;
+
+    update_cart_message(bot, pool, config, chat_id, user_id).await
+}
+
+/// Makes a cart message the chat's tracked one, deleting the one tracked before it.
+async fn adopt_cart_message(
+    bot: &Bot,
+    pool: &SqlitePool,
+    chat_id: ChatId,
+    message_id: MessageId,
+) -> HandlerResult {
+    if tracked_cart_message(pool, chat_id).await? != Some(message_id) {
+        clear_cart_message(bot, pool, chat_id).await?;
+        track_cart_message(pool, chat_id, message_id).await?;
+    }
+
+    Ok(())
+}
+
+/// The cart's text and keyboard, or `None` if the cart is empty.
+async fn render_cart(
+    pool: &SqlitePool,
+    config: &Config,
+    cart: &Cart,
+    user_id: i64,
+) -> Result<Option<(String, InlineKeyboardMarkup)>, Box<dyn std::error::Error + Send + Sync>> {
+    let priced = price_cart(pool, config, cart, user_id).await?;
+
+    if priced.items.is_empty() {
+        return Ok(None);
+    }
+
+    // The prices shown are what checkout compares the catalog against.
+    sqlx::query!(
+        "UPDATE cart_items SET price = (SELECT price FROM products WHERE id = cart_items.product_id)
+        WHERE cart_id = ?",
         cart.id
     )
-    .fetch_all(&pool)
+    .execute(pool)
     .await?;
 
-    if cart_items_with_products.is_empty() {
-        bot.send_message(msg.chat.id, "Your cart is empty.").await?;
-        return Ok(());
+    let (coupon_note, coupon_button) = match &priced.coupon {
+        Some(cart_coupon) => (
+            match &cart_coupon.status {
+                Ok(()) => f!("\n\nCoupon {} applied.", cart_coupon.coupon.code),
+                Err(err) => f!(
+                    "\n\nCoupon {} is not applied: {}",
+                    cart_coupon.coupon.code,
+                    err.message(&config.currency)
+                ),
+            },
+            InlineKeyboardButton::callback("Remove Coupon", "remove_coupon"),
+        ),
+        None => (
+            String::new(),
+            InlineKeyboardButton::callback("Apply Coupon", "apply_coupon"),
+        ),
+    };
+
+    let fulfillment_note = match (&priced.fulfillment_method, priced.minimum_order) {
+        (Some(method), Some(minimum_order)) => f!(
+            "\n\nFulfillment: {method}. {method} requires an order of at least {}.",
+            minimum_order.format(&config.currency)
+        ),
+        (Some(method), None) => f!("\n\nFulfillment: {method}."),
+        (None, _) => String::new(),
+    };
+
+    let fulfillment_buttons = config
+        .fulfillment_methods
+        .iter()
+        .enumerate()
+        .map(|(index, method)| {
+            let label = if Some(method) == priced.fulfillment_method.as_ref() {
+                f!("✓ {method}")
+            } else {
+                method.clone()
+            };
+
+            InlineKeyboardButton::callback(label, f!("fulfillment_method {index}"))
+        })
+        .collect::<Vec<_>>();
+
+    // Methods that deliver offer the viewer's saved addresses.
+    let (address_note, address_buttons) = if config
+        .fulfillment_config(priced.fulfillment_method.as_deref())
+        .requires_address
+    {
+        let addresses = sqlx::query!(
+            r#"SELECT id AS "id!", label FROM addresses WHERE user_id = ? ORDER BY id"#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let note = match fetch_delivery_address(pool, cart.address_id).await? {
+            // Group members only see the label of another member's address.
+            Some(address) if cart.shared => f!("\n\nDeliver to: {}", address.label),
+            Some(address) => f!("\n\nDeliver to: {} - {}", address.label, address.address),
+            None if addresses.is_empty() => "\n\nAdd a delivery address with /profile.".to_owned(),
+            None => "\n\nChoose a delivery address below.".to_owned(),
+        };
+
+        let buttons = addresses
+            .into_iter()
+            .map(|address| {
+                let label = if Some(address.id) == cart.address_id {
+                    f!("✓ {}", address.label)
+                } else {
+                    address.label
+                };
+
+                InlineKeyboardButton::callback(label, f!("cart_address {}", address.id))
+            })
+            .collect::<Vec<_>>();
+
+        (note, buttons)
+    } else {
+        (String::new(), vec![])
+    };
+
+    // Methods limited to the delivery zones ask for a location to check.
+    let (zone_note, location_button) = match &priced.delivery_zone {
+        Some(status) => (
+            match status {
+                ZoneStatus::NoLocation => {
+                    "\n\nShare your location so we can check that we deliver to you.".to_owned()
+                }
+                ZoneStatus::OutOfZone => match pickup_method(config) {
+                    Some((_, method)) => f!(
+                        "\n\nWe don't deliver to the location you shared. Choose {method} instead."
+                    ),
+                    None => "\n\nWe don't deliver to the location you shared.".to_owned(),
+                },
+                ZoneStatus::InZone(zone) => f!(
+                    "\n\nDelivery zone: {}, {} delivery fee.",
+                    zone.name,
+                    zone.fee.format(&config.currency)
+                ),
+            },
+            Some(InlineKeyboardButton::callback(
+                if matches!(status, ZoneStatus::NoLocation) {
+                    "Share Location"
+                } else {
+                    "Change Location"
+                },
+                "share_location",
+            )),
+        ),
+        None => (String::new(), None),
+    };
+
+    let (points_note, points_button) = if priced.points_balance > 0 {
+        let note = if priced.points > 0 {
+            f!(
+                "\n\nRedeeming {} of your {} loyalty points.",
+                priced.points,
+                priced.points_balance
+            )
+        } else {
+            f!("\n\nYou have {} loyalty points.", priced.points_balance)
+        };
+        let label = if cart.use_points {
+            "Don't Use Points".to_owned()
+        } else {
+            f!("Use {} Points", priced.points_balance)
+        };
+
+        (
+            note,
+            Some(InlineKeyboardButton::callback(label, "use_points")),
+        )
+    } else {
+        (String::new(), None)
+    };
+
+    // One row of quantity controls per item, labelled with the item's name and quantity.
+    let mut keyboard = priced
+        .items
+        .iter()
+        .map(|item| {
+            let label = if cart.shared {
+                f!("{} x{} ({})", item.name, item.quantity, item.added_by_name)
+            } else {
+                f!("{} x{}", item.name, item.quantity)
+            };
+
+            vec![
+                InlineKeyboardButton::callback("−", f!("cart_item decrease {}", item.id)),
+                InlineKeyboardButton::callback(label, "noop"),
+                InlineKeyboardButton::callback("+", f!("cart_item increase {}", item.id)),
+                InlineKeyboardButton::callback("✕", f!("cart_item remove {}", item.id)),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let place_order = if cart.shared {
+        "Place Order for Everyone"
+    } else {
+        "Place Order"
+    };
+
+    keyboard.extend([
+        vec![InlineKeyboardButton::callback(place_order, "place_order")],
+        fulfillment_buttons,
+    ]);
+    if !address_buttons.is_empty() {
+        keyboard.push(address_buttons);
+    }
+    if let Some(location_button) = location_button {
+        keyboard.push(vec![location_button]);
     }
+    keyboard.push(
+        [Some(coupon_button), points_button]
+            .into_iter()
+            .flatten()
+            .collect(),
+    );
 
-    bot.send_message(
-        msg.chat.id,
+    // Shared carts show what each member's items come to before promotions.
+    let members_note = if cart.shared {
         f!(
-            "Your cart({}):\n\n#ID - name - quantity - price\n\n--------------------------\n\n{}\n\n--------------------------\n\nTotal: {}",
-            cart_items_with_products.len(),
-            cart_items_with_products
+            "\n\nPer person:\n{}",
+            member_subtotals(&priced.items)
                 .iter()
-                .map(|item| {
-
-                    f!(
-                        "#{} - {} - x{} - {}",
-                        item.id,
-                        item.name,
-                       item.quantity,
-                        format_price(item.price * item.quantity )
-                    )
-                })
+                .map(|(_, name, subtotal)| f!("{name}: {}", subtotal.format(&config.currency)))
                 .collect::<Vec<_>>()
-                .join("\n"),
-            format_price(cart_items_with_products.iter().fold(0, |acc, item| {
-                acc + item.price * item.quantity
-            })),
-        ),
-    )
-    .reply_markup(InlineKeyboardMarkup::new([
-        vec![InlineKeyboardButton::callback("Place Order", "place_order")],
-        vec![
-            InlineKeyboardButton::callback("Remove Item", "remove_cart_item"),
-            InlineKeyboardButton::callback("Edit Quantity", "edit_cart_item_quantity"),
-        ],
-    ]))
-    .await?;
+                .join("\n")
+        )
+    } else {
+        String::new()
+    };
 
-    Ok(())
+    let text = f!(
+        "{}({}):\n\n--------------------------\n\n{}\n\n--------------------------\n\n{}{members_note}{coupon_note}{fulfillment_note}{address_note}{zone_note}{points_note}",
+        if cart.shared { "Group cart" } else { "Your cart" },
+        priced.items.len(),
+        priced
+            .lines
+            .iter()
+            .map(|line| format_line(line, &priced.items, cart.shared, &config.currency))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        format_summary(&priced.summary, &config.currency),
+    );
+
+    Ok(Some((text, InlineKeyboardMarkup::new(keyboard))))
 }
 
-pub async fn place_order_callback(bot: Bot, q: CallbackQuery, pool: SqlitePool) -> HandlerResult {
+/// Handles the −, + and ✕ buttons next to each cart item. Taking the last unit removes the item.
+pub async fn cart_item_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+    action: &str,
+    cart_item_id: i64,
+) -> HandlerResult {
     let user_id = q.from.id.to_string().parse::<i64>()?;
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
 
-    let cart_items = sqlx::query!(
-        "SELECT * FROM cart_items WHERE cart_id = (SELECT id FROM carts WHERE user_id = ?)",
-        user_id
+    let item = sqlx::query!(
+        "SELECT product_id, quantity FROM cart_items WHERE id = ? AND cart_id = ?",
+        cart_item_id,
+        cart.id
     )
-    .fetch_all(&pool)
+    .fetch_optional(&pool)
     .await?;
 
-    if cart_items.is_empty() {
-        bot.send_message(q.chat_id().unwrap(), "Your cart is empty.")
+    let (item, quantity) = match (item, action) {
+        (Some(item), "increase") => {
+            let quantity = item.quantity + 1;
+            (item, quantity)
+        }
+        (Some(item), "decrease") => {
+            let quantity = item.quantity - 1;
+            (item, quantity)
+        }
+        (Some(item), "remove") => (item, 0),
+        _ => {
+            bot.answer_callback_query(q.id).text(NOT_IN_CART).await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(err) = check_cart_quantity(
+        &pool,
+        &config,
+        cart.id,
+        item.product_id,
+        Some(cart_item_id),
+        quantity,
+    )
+    .await?
+    {
+        bot.answer_callback_query(q.id)
+            .text(err.to_string())
             .await?;
         return Ok(());
     }
 
-    let order = sqlx::query!(
-        "INSERT INTO orders (user_id) VALUES (?) RETURNING *",
-        user_id
-    )
-    .fetch_one(&pool)
-    .await?;
-
-    for cart_item in cart_items {
+    if quantity > 0 {
         sqlx::query!(
-            "INSERT INTO order_items (order_id, product_id, quantity) VALUES (?, ?, ?)",
-            order.id,
-            cart_item.product_id,
-            cart_item.quantity
+            "UPDATE cart_items SET quantity = ? WHERE id = ? AND cart_id = ?",
+            quantity,
+            cart_item_id,
+            cart.id
         )
         .execute(&pool)
         .await?;
+    } else {
+        sqlx::query!(
+            "DELETE FROM cart_items WHERE id = ? AND cart_id = ?",
+            cart_item_id,
+            cart.id
+        )
+        .execute(&pool)
+        .await?;
+    }
 
-        sqlx::query!("DELETE FROM cart_items WHERE id = ?", cart_item.id)
-            .execute(&pool)
-            .await?;
+    refresh_cart_message(&bot, &q, &pool, &config).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Renders a cart line as its items, with who added each in shared carts, followed by the
+/// promotions applied to it.
+fn format_line(line: &Line, items: &[CartItem], shared: bool, currency: &Currency) -> String {
+    let mut text = items
+        .iter()
+        .filter(|item| item.product_id == line.product_id)
+        .map(|item| {
+            let added_by = if shared {
+                f!(" ({})", item.added_by_name)
+            } else {
+                String::new()
+            };
+
+            f!(
+                "{} - x{} - {}{added_by}",
+                item.name,
+                item.quantity,
+                item.gross().format(currency)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for adjustment in &line.adjustments {
+        text.push_str(&f!(
+            "\n    {}: -{}",
+            adjustment.name,
+            adjustment.amount.format(currency)
+        ));
     }
 
-    bot.delete_message(q.chat_id().unwrap(), q.clone().message.unwrap().id)
-        .await?;
+    text
+}
 
-    bot.send_message(
-        q.chat_id().unwrap(),
-        "Order placed successfully. use /orders to view your orders.",
+async fn fetch_delivery_address(
+    pool: &SqlitePool,
+    address_id: Option<i64>,
+) -> Result<Option<DeliveryAddress>, sqlx::Error> {
+    let address = sqlx::query!(
+        "SELECT addresses.user_id, addresses.label, addresses.address, users.phone FROM addresses
+        INNER JOIN users ON addresses.user_id = users.id
+        WHERE addresses.id = ?",
+        address_id
     )
+    .fetch_optional(pool)
     .await?;
 
-    Ok(())
+    Ok(address.map(|address| DeliveryAddress {
+        user_id: address.user_id,
+        label: address.label,
+        address: address.address,
+        phone: address.phone,
+    }))
 }
 
-pub async fn remove_cart_item_callback(
+/// Chooses one of the user's saved addresses for delivering the cart.
+pub async fn cart_address_callback(
     bot: Bot,
     q: CallbackQuery,
-    dialogue: AppDialogue,
+    pool: SqlitePool,
+    config: Config,
+    address_id: i64,
 ) -> HandlerResult {
-    dialogue.update(State::ReceiveRemoveCartItemId).await?;
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
 
-    bot.send_message(
-        q.chat_id().unwrap(),
-        "Please, send me the cart item ID (#).",
+    let updated = sqlx::query!(
+        "UPDATE carts SET address_id = ?
+        WHERE id = ? AND EXISTS (SELECT 1 FROM addresses WHERE id = ? AND user_id = ?)",
+        address_id,
+        cart.id,
+        address_id,
+        user_id
     )
-    .reply_markup(ForceReply::default())
+    .execute(&pool)
     .await?;
+
+    if updated.rows_affected() == 0 {
+        bot.answer_callback_query(q.id)
+            .text("That address is not in your profile.")
+            .await?;
+        return Ok(());
+    }
+
+    refresh_cart_message(&bot, &q, &pool, &config).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
     Ok(())
 }
 
-pub async fn receive_remove_cart_item_id(
+/// Each member's user id, name and subtotal before promotions, in the order they first added
+/// something.
+fn member_subtotals(items: &[CartItem]) -> Vec<(i64, String, Money)> {
+    let mut members: Vec<(i64, String, Money)> = vec![];
+
+    for item in items {
+        match members.iter_mut().find(|(id, _, _)| *id == item.added_by) {
+            Some((_, _, subtotal)) => {
+                *subtotal = subtotal.checked_add(item.gross()).unwrap_or_default();
+            }
+            None => members.push((item.added_by, item.added_by_name.clone(), item.gross())),
+        }
+    }
+
+    members
+}
+
+/// Prices a cart with the configured promotions, its coupon, tax, the fulfillment fee or its
+/// delivery zone's fee, and `user_id`'s loyalty points. The cart view, coupon entry and checkout
+/// all go through this so they always agree.
+pub async fn price_cart(
+    pool: &SqlitePool,
+    config: &Config,
+    cart: &Cart,
+    user_id: i64,
+) -> Result<PricedCart, Box<dyn std::error::Error + Send + Sync>> {
+    let (items, lines) = fetch_cart_items(pool, cart.id).await?;
+
+    let clock = Clock {
+        now: chrono::Local::now().time(),
+        close: config.closing_time(),
+    };
+    let lines = apply_promotions(&lines, &config.promotions, clock);
+
+    let net = summarize(&lines, &config.tax, None)
+        .ok_or_else(|| eyre::eyre!("cart total overflowed"))?
+        .after_promotions();
+
+    let coupon = fetch_cart_coupon(pool, cart.coupon_id, user_id, net).await?;
+
+    let fulfillment_method = config.fulfillment_method(cart.fulfillment_method.as_deref());
+    let mut fulfillment = config.fulfillment_config(fulfillment_method);
+
+    let delivery_zone = (fulfillment.requires_address && config.has_delivery_zones()).then(|| {
+        match cart.location.map(|location| config.delivery_zone(location)) {
+            Some(Some(zone)) => ZoneStatus::InZone(zone.clone()),
+            Some(None) => ZoneStatus::OutOfZone,
+            None => ZoneStatus::NoLocation,
+        }
+    });
+    if let Some(ZoneStatus::InZone(zone)) = &delivery_zone {
+        fulfillment.fee = zone.fee;
+    }
+
+    let summary = summarize(
+        &lines,
+        &config.tax,
+        coupon.as_ref().and_then(CartCoupon::discount),
+    )
+    .and_then(|summary| summary.with_fee(&fulfillment))
+    .ok_or_else(|| eyre::eyre!("cart total overflowed"))?;
+
+    let minimum_order = Some(fulfillment.minimum_order)
+        .filter(|minimum_order| summary.after_discounts() < *minimum_order);
+
+    // Points are only redeemed on the customer's own cart.
+    let points_balance = if config.loyalty.enabled && !cart.shared {
+        fetch_points_balance(pool, user_id).await?
+    } else {
+        0
+    };
+    let (points, summary) = if cart.use_points {
+        let (points, value) = config.loyalty.redeem(points_balance, summary.total);
+        (points, summary.with_points(value))
+    } else {
+        (0, summary)
+    };
+
+    Ok(PricedCart {
+        items,
+        lines,
+        coupon,
+        fulfillment_method: fulfillment_method.map(ToOwned::to_owned),
+        minimum_order,
+        delivery_zone,
+        points,
+        points_balance,
+        summary,
+    })
+}
+
+/// Loads a cart's items, and its lines at catalog prices with the units of each product added
+/// up, so promotions see everything the cart holds.
+async fn fetch_cart_items(
+    pool: &SqlitePool,
+    cart_id: i64,
+) -> Result<(Vec<CartItem>, Vec<Line>), sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT cart_items.id, cart_items.product_id, cart_items.quantity, cart_items.added_by,
+            users.first_name AS "added_by_name?", products.name, products.category, products.price
+        FROM cart_items
+        INNER JOIN products ON cart_items.product_id = products.id
+        LEFT JOIN users ON cart_items.added_by = users.id
+        WHERE cart_items.cart_id = ?
+        ORDER BY cart_items.id"#,
+        cart_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut items = vec![];
+    let mut lines: Vec<Line> = vec![];
+
+    for row in rows {
+        match lines
+            .iter_mut()
+            .find(|line| line.product_id == row.product_id)
+        {
+            Some(line) => line.quantity += row.quantity,
+            None => lines.push(Line {
+                product_id: row.product_id,
+                name: row.name.clone(),
+                category: row.category,
+                unit_price: Money::from_cents(row.price),
+                quantity: row.quantity,
+                adjustments: vec![],
+            }),
+        }
+
+        items.push(CartItem {
+            id: row.id,
+            product_id: row.product_id,
+            name: row.name,
+            unit_price: Money::from_cents(row.price),
+            quantity: row.quantity,
+            added_by: row.added_by,
+            added_by_name: row.added_by_name.unwrap_or_default(),
+        });
+    }
+
+    Ok((items, lines))
+}
+
+/// Renders the subtotal, promotions, discount, tax, fee and total lines shared by the cart, receipts
+/// and order details.
+pub fn format_summary(summary: &Summary, currency: &Currency) -> String {
+    let tax_label = if summary.tax_included {
+        "Tax (included)"
+    } else {
+        "Tax"
+    };
+
+    let mut text = f!("Subtotal: {}\n", summary.subtotal.format(currency));
+
+    if summary.promotions > Money::ZERO {
+        text.push_str(&f!(
+            "Promotions: -{}\n",
+            summary.promotions.format(currency)
+        ));
+    }
+    if summary.discount > Money::ZERO {
+        text.push_str(&f!("Discount: -{}\n", summary.discount.format(currency)));
+    }
+
+    text.push_str(&f!("{tax_label}: {}\n", summary.tax.format(currency)));
+
+    if summary.fee > Money::ZERO {
+        text.push_str(&f!("Fulfillment fee: {}\n", summary.fee.format(currency)));
+    }
+    if summary.points > Money::ZERO {
+        text.push_str(&f!("Points: -{}\n", summary.points.format(currency)));
+    }
+
+    text.push_str(&f!("Total: {}", summary.total.format(currency)));
+
+    text
+}
+
+pub async fn place_order_callback(
     bot: Bot,
-    msg: Message,
+    q: CallbackQuery,
     pool: SqlitePool,
-    dialogue: AppDialogue,
+    config: Config,
 ) -> HandlerResult {
-    match msg.text().map(ToOwned::to_owned) {
-        Some(cart_item_id) => {
-            tracing::info!("cart_item_id: {}", cart_item_id);
-
-            let cart_item_id = match cart_item_id.parse::<i64>() {
-                Ok(cart_item_id) => cart_item_id,
-                Err(_) => {
-                    bot.send_message(msg.chat.id, "Invalid cart item id.")
-                        .await?;
-                    return Ok(());
-                }
-            };
+    let user_id = q.from.id.to_string().parse::<i64>()?;
 
-            match sqlx::query!("DELETE FROM cart_items WHERE id = ?", cart_item_id)
-                .execute(&pool)
-                .await
-            {
-                Err(_) => {
-                    bot.send_message(msg.chat.id, "Invalid cart item id.")
-                        .await?;
-                    return Ok(());
-                }
-                Ok(_) => {
-                    bot.send_message(msg.chat.id, "Cart item removed successfully.")
-                        .await?;
+    if is_restricted(&pool, user_id).await? {
+        bot.answer_callback_query(q.id)
+            .text(RESTRICTED)
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
 
-                    dialogue.exit().await?;
-                }
-            };
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
+
+    let changes = revalidate_cart(&pool, cart.id).await?;
+    if !changes.is_empty() {
+        bot.send_message(
+            q.chat_id().unwrap(),
+            f!(
+                "Your cart changed since you last saw it:\n\n{}\n\nPlease review your cart and place the order again.",
+                changes
+                    .iter()
+                    .map(|change| change.describe(&config.currency))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        )
+        .reply_markup(InlineKeyboardMarkup::new([vec![
+            InlineKeyboardButton::callback("Place Order", "place_order"),
+        ]]))
+        .await?;
+
+        update_cart_message(&bot, &pool, &config, q.chat_id().unwrap(), user_id).await?;
+
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    }
+
+    let priced = price_cart(&pool, &config, &cart, user_id).await?;
+
+    if priced.items.is_empty() {
+        bot.send_message(q.chat_id().unwrap(), empty_cart_text(&cart))
+            .await?;
+        return Ok(());
+    }
+
+    if let Err(err) = check_cart_quantities(&pool, &config, cart.id).await? {
+        bot.send_message(q.chat_id().unwrap(), err.to_string())
+            .await?;
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    }
+
+    // A coupon that stopped applying since it was added must be removed before checkout.
+    if let Some(CartCoupon {
+        coupon,
+        status: Err(err),
+        ..
+    }) = &priced.coupon
+    {
+        bot.send_message(
+            q.chat_id().unwrap(),
+            coupon_error_text(coupon, err, &config.currency),
+        )
+        .await?;
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    }
+
+    if let (Some(method), Some(minimum_order)) = (&priced.fulfillment_method, priced.minimum_order)
+    {
+        bot.send_message(
+            q.chat_id().unwrap(),
+            f!(
+                "{method} requires an order of at least {}.",
+                minimum_order.format(&config.currency)
+            ),
+        )
+        .await?;
+        bot.answer_callback_query(q.id).await?;
+        return Ok(());
+    }
+
+    let delivery_zone = match priced.delivery_zone {
+        Some(ZoneStatus::InZone(zone)) => Some(zone.name),
+        Some(ZoneStatus::NoLocation) => {
+            bot.send_message(
+                q.chat_id().unwrap(),
+                "Please, share your location from your cart so we can check that we deliver to you.",
+            )
+            .await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        Some(ZoneStatus::OutOfZone) => {
+            send_out_of_zone(&bot, q.chat_id().unwrap(), &config).await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    // The address and phone number are copied so the order keeps where it was delivered. Both
+    // must be the orderer's own, so a group member can't order to another member's address.
+    let (phone, address) = if config
+        .fulfillment_config(priced.fulfillment_method.as_deref())
+        .requires_address
+    {
+        match fetch_delivery_address(&pool, cart.address_id).await? {
+            Some(DeliveryAddress {
+                user_id: owner_id,
+                phone: Some(phone),
+                address,
+                ..
+            }) if owner_id == user_id => (Some(phone), Some(address)),
+            selected => {
+                let message = match selected {
+                    None => "Please, choose a delivery address in your cart. Add one with /profile.",
+                    Some(selected) if selected.user_id != user_id => {
+                        "The delivery address belongs to another member. Only they can place this order, or choose your own address."
+                    }
+                    Some(_) => {
+                        "Please, share a phone number with /profile so we can contact you about the delivery."
+                    }
+                };
+
+                bot.send_message(q.chat_id().unwrap(), message).await?;
+                bot.answer_callback_query(q.id).await?;
+                return Ok(());
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    let summary = priced.summary;
+    let coupon_id = priced.coupon.as_ref().map(|cart_coupon| cart_coupon.id);
+    let (subtotal, promotions, discount, tax, fee, points_discount, total) = (
+        summary.subtotal.cents(),
+        summary.promotions.cents(),
+        summary.discount.cents(),
+        summary.tax.cents(),
+        summary.fee.cents(),
+        summary.points.cents(),
+        summary.total.cents(),
+    );
+
+    let chat_id = cart.shared.then(|| q.chat_id().unwrap().0);
+
+    let mut tx = pool.begin().await?;
+
+    let order = sqlx::query!(
+        "INSERT INTO orders (user_id, chat_id, currency, subtotal, promotions, coupon_id, discount,
+            tax, tax_included, fulfillment_method, delivery_fee, total, phone, address, delivery_zone,
+            points_discount)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        user_id,
+        chat_id,
+        config.currency.code,
+        subtotal,
+        promotions,
+        coupon_id,
+        discount,
+        tax,
+        summary.tax_included,
+        priced.fulfillment_method,
+        fee,
+        total,
+        phone,
+        address,
+        delivery_zone,
+        points_discount
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // The coupon's limits are checked again once the order holds the database's write lock, so
+    // two checkouts can't both take its last use.
+    if let Some(CartCoupon { id, coupon, .. }) = &priced.coupon {
+        let (uses, user_uses) = fetch_coupon_uses(&mut *tx, *id, user_id, Some(order.id)).await?;
+
+        if let Err(err) = coupon.check(
+            summary.after_promotions(),
+            chrono::Local::now().date_naive(),
+            uses,
+            user_uses,
+        ) {
+            tx.rollback().await?;
+
+            bot.send_message(
+                q.chat_id().unwrap(),
+                coupon_error_text(coupon, &err, &config.currency),
+            )
+            .await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+    }
+
+    if priced.points > 0 {
+        record_points(
+            &mut *tx,
+            user_id,
+            order.id,
+            -priced.points,
+            PointsReason::Redeemed,
+        )
+        .await?;
+
+        // Like the coupon, the balance is checked again under the write lock, so placing the
+        // order twice at once can't redeem the same points twice.
+        if fetch_points_balance(&mut *tx, user_id).await? < 0 {
+            tx.rollback().await?;
+
+            bot.send_message(
+                q.chat_id().unwrap(),
+                "Your points balance changed. Please review your cart and place the order again.",
+            )
+            .await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+    }
+
+    for item in priced.items {
+        // The unit price is copied so the order keeps what the customer paid, and who added the
+        // item so a shared order's bill can be split.
+        let price = item.unit_price.cents();
+        sqlx::query!(
+            "INSERT INTO order_items (order_id, product_id, quantity, price, added_by)
+            VALUES (?, ?, ?, ?, ?)",
+            order.id,
+            item.product_id,
+            item.quantity,
+            price,
+            item.added_by
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM cart_items WHERE id = ?", item.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE carts SET coupon_id = NULL, use_points = FALSE WHERE id = ?",
+        cart.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    // The cart message goes away with the items it listed.
+    if let Some(message) = &q.message {
+        adopt_cart_message(&bot, &pool, message.chat.id, message.id).await?;
+    }
+    clear_cart_message(&bot, &pool, q.chat_id().unwrap()).await?;
+
+    if cart.shared {
+        bot.send_message(
+            q.chat_id().unwrap(),
+            "Order placed successfully for everyone. Split the bill to send each member their share privately.",
+        )
+        .reply_markup(InlineKeyboardMarkup::new([vec![
+            InlineKeyboardButton::callback("Split the Bill", f!("split_bill {}", order.id)),
+        ]]))
+        .await?;
+    } else {
+        bot.send_message(
+            q.chat_id().unwrap(),
+            "Order placed successfully. use /orders to view your orders.",
+        )
+        .await?;
+    }
+
+    send_order_detail(&bot, q.chat_id().unwrap(), &pool, &config, order.id).await?;
+
+    // The group's receipt leaves out the delivery details, so the orderer gets them privately.
+    if cart.shared && address.is_some() {
+        if let Err(err) = send_order_detail(&bot, ChatId(user_id), &pool, &config, order.id).await {
+            tracing::warn!(
+                "Failed to send order {} details to user {}: {}",
+                order.id,
+                user_id,
+                err
+            );
+        }
+    }
+
+    notify_staff(&bot, &pool, &config, order.id).await;
+
+    Ok(())
+}
+
+fn coupon_error_text(coupon: &Coupon, err: &CouponError, currency: &Currency) -> String {
+    f!(
+        "Coupon {} can't be applied: {} Remove it from your cart to place the order.",
+        coupon.code,
+        err.message(currency)
+    )
+}
+
+/// The first fulfillment method that doesn't deliver, with its index, offered to customers
+/// outside the delivery zones.
+fn pickup_method(config: &Config) -> Option<(usize, &String)> {
+    config
+        .fulfillment_methods
+        .iter()
+        .enumerate()
+        .find(|(_, method)| !config.fulfillment_config(Some(method)).requires_address)
+}
+
+async fn send_out_of_zone(bot: &Bot, chat_id: ChatId, config: &Config) -> HandlerResult {
+    match pickup_method(config) {
+        Some((index, method)) => {
+            bot.send_message(
+                chat_id,
+                f!("Sorry, we don't deliver to that location. You can choose {method} instead."),
+            )
+            .reply_markup(InlineKeyboardMarkup::new([vec![
+                InlineKeyboardButton::callback(
+                    f!("Switch to {method}"),
+                    f!("fulfillment_method {index}"),
+                ),
+            ]]))
+            .await?;
         }
         None => {
-            bot.send_message(msg.chat.id, "Please, send me the cart item id.")
+            bot.send_message(chat_id, "Sorry, we don't deliver to that location.")
                 .await?;
         }
     }
+
     Ok(())
 }
 
-pub async fn edit_cart_item_quantity_callback(
+/// Asks for the location to check the cart against the delivery zones.
+pub async fn share_location_callback(
     bot: Bot,
     q: CallbackQuery,
     dialogue: AppDialogue,
 ) -> HandlerResult {
-    dialogue
-        .update(State::ReceiveEditCartItemQuantityId)
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    dialogue.update(State::ReceiveDeliveryLocation).await?;
+
+    // Location buttons only work in private chats.
+    if chat_id.is_user() {
+        bot.send_message(
+            chat_id,
+            "Please, send me your delivery location with the button below.",
+        )
+        .reply_markup(
+            KeyboardMarkup::new([[
+                KeyboardButton::new("Share Location").request(ButtonRequest::Location)
+            ]])
+            .resize_keyboard(true)
+            .one_time_keyboard(true),
+        )
+        .await?;
+    } else {
+        bot.send_message(
+            chat_id,
+            "Please, send me the delivery location as an attachment.",
+        )
+        .reply_markup(ForceReply::default())
         .await?;
+    }
+
+    bot.answer_callback_query(q.id).await?;
 
-    bot.send_message(
-        q.chat_id().unwrap(),
-        "Please, send me the cart item ID (#).",
-    )
-    .reply_markup(ForceReply::default())
-    .await?;
     Ok(())
 }
 
-pub async fn receive_edit_cart_item_quantity_id(
+pub async fn receive_delivery_location(
     bot: Bot,
     msg: Message,
     dialogue: AppDialogue,
+    pool: SqlitePool,
+    config: Config,
 ) -> HandlerResult {
-    match msg.text().map(ToOwned::to_owned) {
-        Some(cart_item_id) => {
-            tracing::info!("cart_item_id: {}", cart_item_id);
-
-            let cart_item_id = match cart_item_id.parse::<i64>() {
-                Ok(cart_item_id) => cart_item_id,
-                Err(_) => {
-                    bot.send_message(msg.chat.id, "Invalid cart item id.")
-                        .await?;
-                    return Ok(());
-                }
-            };
-
-            dialogue
-                .update(State::ReceiveEditCartItemQuantityAmount { cart_item_id })
+    let location = match msg.location() {
+        Some(location) => Location {
+            latitude: location.latitude,
+            longitude: location.longitude,
+        },
+        None => {
+            bot.send_message(msg.chat.id, "Please, send me a location, or /cancel.")
                 .await?;
+            return Ok(());
+        }
+    };
 
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    let cart = fetch_cart(&pool, Some(msg.chat.id), user_id).await?;
+
+    sqlx::query!(
+        "UPDATE carts SET latitude = ?, longitude = ? WHERE id = ?",
+        location.latitude,
+        location.longitude,
+        cart.id
+    )
+    .execute(&pool)
+    .await?;
+
+    dialogue.exit().await?;
+
+    match config.delivery_zone(location) {
+        Some(zone) => {
             bot.send_message(
                 msg.chat.id,
-                format!(
-                    "Please, send me the new quantity for cart item #{}.",
-                    cart_item_id
+                f!(
+                    "We deliver there! You're in the {} zone, delivery costs {}.",
+                    zone.name,
+                    zone.fee.format(&config.currency)
                 ),
             )
-            .reply_markup(ForceReply::default())
+            .reply_markup(KeyboardRemove::new())
             .await?;
         }
         None => {
-            bot.send_message(msg.chat.id, "Please, send me the cart item id.")
+            if msg.chat.id.is_user() {
+                bot.send_message(msg.chat.id, "Location received.")
+                    .reply_markup(KeyboardRemove::new())
+                    .await?;
+            }
+            send_out_of_zone(&bot, msg.chat.id, &config).await?;
+        }
+    }
+
+    update_cart_message(&bot, &pool, &config, msg.chat.id, user_id).await
+}
+
+/// Compares a cart with the catalog before checkout. Items that can no longer be ordered are
+/// removed and changed prices are recorded as seen, so the next attempt goes through.
+async fn revalidate_cart(pool: &SqlitePool, cart_id: i64) -> Result<Vec<CartChange>, sqlx::Error> {
+    let items = sqlx::query!(
+        r#"SELECT cart_items.id, cart_items.price AS seen_price, products.name, products.price,
+            (products.visible AND NOT products.archived AND products.in_stock) AS "available!: bool"
+        FROM cart_items
+        INNER JOIN products ON cart_items.product_id = products.id
+        WHERE cart_items.cart_id = ?
+        ORDER BY cart_items.id"#,
+        cart_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut changes = vec![];
+
+    for item in items {
+        let snapshot = ItemSnapshot {
+            name: item.name,
+            seen_price: Money::from_cents(item.seen_price),
+            price: Money::from_cents(item.price),
+            available: item.available,
+        };
+
+        let change = match snapshot.change() {
+            Some(change) => change,
+            None => continue,
+        };
+
+        match change {
+            CartChange::Unavailable { .. } => {
+                sqlx::query!("DELETE FROM cart_items WHERE id = ?", item.id)
+                    .execute(pool)
+                    .await?;
+            }
+            CartChange::PriceChanged { .. } => {
+                sqlx::query!(
+                    "UPDATE cart_items SET price = ? WHERE id = ?",
+                    item.price,
+                    item.id
+                )
+                .execute(pool)
                 .await?;
+            }
+        }
+
+        // Members of a shared cart may each have the product, but it changed once.
+        if !changes.contains(&change) {
+            changes.push(change);
         }
     }
-    Ok(())
+
+    Ok(changes)
 }
 
-pub async fn receive_edit_cart_item_quantity_amount(
+const NOT_IN_CART: &str = "That item is not in your cart.";
+
+pub async fn fulfillment_method_callback(
     bot: Bot,
-    msg: Message,
-    cart_item_id: i64,
-    dialogue: AppDialogue,
+    q: CallbackQuery,
     pool: SqlitePool,
+    config: Config,
+    index: usize,
 ) -> HandlerResult {
-    match msg.text().map(ToOwned::to_owned) {
-        Some(quantity) => {
-            tracing::info!("quantity: {}", quantity);
-
-            let quantity = match quantity.parse::<i64>() {
-                Ok(quantity) => quantity,
-                Err(_) => {
-                    bot.send_message(msg.chat.id, "Invalid quantity.").await?;
-                    return Ok(());
-                }
-            };
-
-            match sqlx::query!(
-                "UPDATE cart_items SET quantity = ? WHERE id = ?",
-                quantity,
-                cart_item_id
-            )
-            .execute(&pool)
-            .await
-            {
-                Err(_) => {
-                    bot.send_message(msg.chat.id, "Invalid cart item id.")
-                        .await?;
-                    return Ok(());
-                }
-                Ok(_) => {
-                    bot.send_message(msg.chat.id, "Cart item updated successfully.")
-                        .await?;
+    let user_id = q.from.id.to_string().parse::<i64>()?;
 
-                    dialogue.exit().await?;
-                }
-            };
-        }
+    let method = match config.fulfillment_methods.get(index) {
+        Some(method) => method,
         None => {
-            bot.send_message(msg.chat.id, "Please, send me the quantity.")
-                .await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
         }
+    };
+
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
+
+    sqlx::query!(
+        "UPDATE carts SET fulfillment_method = ? WHERE id = ?",
+        method,
+        cart.id
+    )
+    .execute(&pool)
+    .await?;
+
+    refresh_cart_message(&bot, &q, &pool, &config).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Turns redeeming the customer's loyalty points on the cart on or off.
+pub async fn use_points_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
+
+    if cart.shared {
+        bot.answer_callback_query(q.id)
+            .text("Points can only be redeemed on your own cart.")
+            .await?;
+        return Ok(());
     }
+
+    sqlx::query!(
+        "UPDATE carts SET use_points = NOT use_points WHERE id = ?",
+        cart.id
+    )
+    .execute(&pool)
+    .await?;
+
+    refresh_cart_message(&bot, &q, &pool, &config).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
     Ok(())
 }
+
+/// Checks setting a cart item of `product_id` to `quantity`, or adding one when `cart_item_id` is
+/// `None`, against the product's and the store's limits. The product's limit counts the units
+/// every member of a shared cart added.
+pub async fn check_cart_quantity(
+    pool: &SqlitePool,
+    config: &Config,
+    cart_id: i64,
+    product_id: i64,
+    cart_item_id: Option<i64>,
+    quantity: i64,
+) -> Result<Result<(), QuantityError>, sqlx::Error> {
+    if quantity < 0 {
+        return Ok(Err(QuantityError::Negative));
+    }
+
+    let product = sqlx::query!(
+        "SELECT name, max_quantity FROM products WHERE id = ?",
+        product_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let items = sqlx::query!(
+        "SELECT id, product_id, quantity FROM cart_items WHERE cart_id = ?",
+        cart_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let (mut current_units, mut same_product_units, mut other_units) = (0, 0, 0);
+    for item in items {
+        if Some(item.id) == cart_item_id {
+            current_units += item.quantity;
+        } else if item.product_id == product_id {
+            same_product_units += item.quantity;
+        } else {
+            other_units += item.quantity;
+        }
+    }
+
+    let item = ItemQuantity {
+        name: product.name,
+        quantity: same_product_units + current_units,
+        max_quantity: product.max_quantity,
+    };
+
+    Ok(check_quantity(
+        &item,
+        same_product_units + quantity,
+        other_units,
+        config.max_order_quantity,
+    ))
+}
+
+/// Checks every item of a cart against the limits, which may have changed since it was filled.
+async fn check_cart_quantities(
+    pool: &SqlitePool,
+    config: &Config,
+    cart_id: i64,
+) -> Result<Result<(), QuantityError>, sqlx::Error> {
+    let items = sqlx::query!(
+        r#"SELECT products.name, SUM(cart_items.quantity) AS "quantity!: i64",
+            products.max_quantity
+        FROM cart_items
+        INNER JOIN products ON cart_items.product_id = products.id
+        WHERE cart_items.cart_id = ?
+        GROUP BY products.id"#,
+        cart_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|item| ItemQuantity {
+        name: item.name,
+        quantity: item.quantity,
+        max_quantity: item.max_quantity,
+    })
+    .collect::<Vec<_>>();
+
+    Ok(check_cart(&items, config.max_order_quantity))
+}
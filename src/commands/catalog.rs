@@ -0,0 +1,288 @@
+use crate::catalog::{parse_catalog, write_catalog, CatalogRow, RowError};
+use crate::commands::inventory::fetch_product_images;
+use crate::money::Money;
+use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::{assert_admin_id, upload_image_url};
+use crate::State;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::net::Download;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+use url::Url;
+
+/// Validates a catalog CSV sent as a document and previews the import before applying it.
+pub async fn receive_catalog_document(
+    bot: Bot,
+    msg: Message,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+) -> HandlerResult {
+    tracing::info!("processing catalog document in chat {}", msg.chat.id);
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    let document = match msg.document() {
+        Some(document) => document,
+        None => return Ok(()),
+    };
+
+    let file = bot.get_file(&document.file.id).await?;
+    let mut data = vec![];
+    bot.download_file(&file.path, &mut data).await?;
+
+    let mut rows = match parse_catalog(&data) {
+        Ok(rows) => rows,
+        Err(errors) => {
+            bot.send_message(msg.chat.id, validation_report(&errors))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut errors = vec![];
+    let mut updated = 0;
+
+    for (index, row) in rows.iter_mut().enumerate() {
+        if let Some(product_id) = row.id {
+            let product = sqlx::query!("SELECT archived FROM products WHERE id = ?", product_id)
+                .fetch_optional(&pool)
+                .await?;
+
+            let message = match product {
+                None => Some(f!("unknown product id {product_id}")),
+                Some(product) if product.archived => Some(f!(
+                    "product {product_id} is archived, restore it with /restore first"
+                )),
+                Some(_) => None,
+            };
+
+            if let Some(message) = message {
+                errors.push(RowError {
+                    row: index + 2,
+                    message,
+                });
+            }
+
+            updated += 1;
+        }
+
+        // Image URLs are fetched once now, so the import stores the uploaded photos' file_id.
+        for image in row.images.iter_mut() {
+            if Url::parse(image).is_err() {
+                continue;
+            }
+
+            match upload_image_url(&bot, msg.chat.id, image).await {
+                Some(file_id) => *image = file_id,
+                None => errors.push(RowError {
+                    row: index + 2,
+                    message: f!("couldn't load the image {image}"),
+                }),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        bot.send_message(msg.chat.id, validation_report(&errors))
+            .await?;
+        return Ok(());
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        f!(
+            "Dry run: {} new and {updated} updated products. Nothing has been changed yet.",
+            rows.len() - updated
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([vec![
+        InlineKeyboardButton::callback("Import", "import_catalog confirm"),
+        InlineKeyboardButton::callback("Cancel", "import_catalog cancel"),
+    ]]))
+    .await?;
+
+    dialogue
+        .update(State::ReceiveCatalogImportConfirmation { rows })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn import_catalog_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    action: &str,
+) -> HandlerResult {
+    let admin_id = q.from.id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    if let Some(message) = &q.message {
+        bot.delete_message(chat_id, message.id).await?;
+    }
+
+    let rows = match dialogue.get().await? {
+        Some(State::ReceiveCatalogImportConfirmation { rows }) if action == "confirm" => rows,
+        _ => {
+            dialogue.exit().await?;
+
+            bot.send_message(chat_id, "Catalog import cancelled.")
+                .await?;
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+
+    for (index, row) in rows.iter().enumerate() {
+        let price = row.price.cents();
+        let product_id = match row.id {
+            Some(product_id) => {
+                let result = sqlx::query!(
+                    "UPDATE products SET name = ?, description = ?, price = ?, category = ?, visible = ?
+                    WHERE id = ? AND NOT archived",
+                    row.name,
+                    row.description,
+                    price,
+                    row.category,
+                    row.visible,
+                    product_id
+                )
+                .execute(&mut *tx)
+                .await?;
+
+                // The product was removed or archived since the dry run.
+                if result.rows_affected() == 0 {
+                    tx.rollback().await?;
+                    dialogue.exit().await?;
+
+                    let error = RowError {
+                        row: index + 2,
+                        message: f!("product {product_id} no longer exists or was archived"),
+                    };
+                    bot.send_message(chat_id, validation_report(&[error]))
+                        .await?;
+                    bot.answer_callback_query(q.id).await?;
+                    return Ok(());
+                }
+
+                product_id
+            }
+            None => {
+                sqlx::query!(
+                    "INSERT INTO products (name, description, price, category, visible)
+                    VALUES (?, ?, ?, ?, ?) RETURNING id",
+                    row.name,
+                    row.description,
+                    price,
+                    row.category,
+                    row.visible
+                )
+                .fetch_one(&mut *tx)
+                .await?
+                .id
+            }
+        };
+
+        // An empty images column keeps the product's current gallery.
+        if !row.images.is_empty() {
+            sqlx::query!(
+                "DELETE FROM product_images WHERE product_id = ?",
+                product_id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            for (position, image) in row.images.iter().enumerate() {
+                let position = position as i64;
+
+                sqlx::query!(
+                    "INSERT INTO product_images (product_id, file_id, position) VALUES (?, ?, ?)",
+                    product_id,
+                    image,
+                    position
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    tracing::info!("admin {} imported {} catalog rows", admin_id, rows.len());
+
+    dialogue.exit().await?;
+
+    bot.send_message(chat_id, f!("Imported {} products.", rows.len()))
+        .await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+pub async fn export_catalog(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+    tracing::info!("processing /export_catalog command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    let products = sqlx::query!("SELECT * FROM products WHERE NOT archived ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+
+    let mut rows = vec![];
+
+    for product in products {
+        rows.push(CatalogRow {
+            id: Some(product.id),
+            name: product.name,
+            description: product.description,
+            price: Money::from_cents(product.price),
+            category: product.category,
+            images: fetch_product_images(&pool, product.id).await?,
+            visible: product.visible,
+        });
+    }
+
+    bot.send_document(
+        msg.chat.id,
+        InputFile::memory(write_catalog(&rows)?).file_name("catalog.csv"),
+    )
+    .caption(f!("Catalog export: {} products.", rows.len()))
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the row errors, keeping the message within Telegram's length limit.
+fn validation_report(errors: &[RowError]) -> String {
+    const MAX_ERRORS: usize = 30;
+
+    let mut report = errors
+        .iter()
+        .take(MAX_ERRORS)
+        .map(|error| f!("Row {}: {}", error.row, error.message))
+        .collect::<Vec<_>>();
+
+    if errors.len() > MAX_ERRORS {
+        report.push(f!("...and {} more.", errors.len() - MAX_ERRORS));
+    }
+
+    f!("The catalog was not imported:\n\n{}", report.join("\n"))
+}
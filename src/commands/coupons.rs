@@ -0,0 +1,337 @@
+use crate::commands::cart::{
+    fetch_cart, price_cart, refresh_cart_message, update_cart_message, Cart,
+};
+use crate::coupons::{parse_coupon, Coupon, CouponError};
+use crate::money::Money;
+use crate::pricing::Discount;
+use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::{assert_admin_id, Config};
+use crate::State;
+use chrono::NaiveDate;
+use format as f;
+use sqlx::{SqliteExecutor, SqlitePool};
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{prelude::*, types::ForceReply};
+
+/// The coupon applied to a cart and whether it can currently be redeemed.
+pub struct CartCoupon {
+    pub id: i64,
+    pub coupon: Coupon,
+    pub status: Result<(), CouponError>,
+}
+
+impl CartCoupon {
+    /// The discount to price the cart with, if the coupon can be redeemed.
+    pub fn discount(&self) -> Option<&Discount> {
+        self.status.is_ok().then_some(&self.coupon.discount)
+    }
+}
+
+/// Creates or updates a coupon from the command arguments, or lists the coupons without any.
+pub async fn coupon(
+    bot: Bot,
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+    args: String,
+) -> HandlerResult {
+    tracing::info!("processing /coupon command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    if args.trim().is_empty() {
+        return list_coupons(&bot, msg.chat.id, &pool, &config).await;
+    }
+
+    let coupon = match parse_coupon(&args, &config.currency) {
+        Ok(coupon) => coupon,
+        Err(err) => {
+            bot.send_message(
+                msg.chat.id,
+                f!("Invalid coupon: {err}.\n\nExample: /coupon SUMMER10 10% min=20 expires=2026-08-31 uses=100 per_user=1"),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let (kind, value) = match coupon.discount {
+        Discount::Percent(percent) => ("percent", percent),
+        Discount::Fixed(amount) => ("fixed", amount.cents()),
+    };
+    let min_order = coupon.min_order.cents();
+
+    sqlx::query!(
+        "INSERT INTO coupons (code, kind, value, min_order, expires_on, max_uses, max_uses_per_user)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (code) DO UPDATE SET kind = excluded.kind, value = excluded.value,
+            min_order = excluded.min_order, expires_on = excluded.expires_on,
+            max_uses = excluded.max_uses, max_uses_per_user = excluded.max_uses_per_user",
+        coupon.code,
+        kind,
+        value,
+        min_order,
+        coupon.expires_on,
+        coupon.max_uses,
+        coupon.max_uses_per_user
+    )
+    .execute(&pool)
+    .await?;
+
+    tracing::info!("admin {} saved coupon {}", id, coupon.code);
+
+    bot.send_message(
+        msg.chat.id,
+        f!("Coupon saved: {}", coupon.describe(&config.currency)),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn list_coupons(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    config: &Config,
+) -> HandlerResult {
+    let rows = sqlx::query!(
+        r#"SELECT coupons.*,
+            (SELECT COUNT(*) FROM orders
+                WHERE orders.coupon_id = coupons.id AND NOT orders.cancelled) AS "uses!: i64"
+        FROM coupons ORDER BY code"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        bot.send_message(
+            chat_id,
+            "There are no coupons. Create one with /coupon CODE 10% or /coupon CODE 5.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let coupons = rows
+        .into_iter()
+        .map(|row| {
+            let coupon = coupon_from_row(
+                row.code,
+                &row.kind,
+                row.value,
+                row.min_order,
+                row.expires_on,
+                row.max_uses,
+                row.max_uses_per_user,
+            );
+
+            f!("{} ({} used)", coupon.describe(&config.currency), row.uses)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    bot.send_message(chat_id, f!("Coupons:\n\n{coupons}"))
+        .await?;
+
+    Ok(())
+}
+
+fn coupon_from_row(
+    code: String,
+    kind: &str,
+    value: i64,
+    min_order: i64,
+    expires_on: Option<NaiveDate>,
+    max_uses: Option<i64>,
+    max_uses_per_user: Option<i64>,
+) -> Coupon {
+    let discount = match kind {
+        "percent" => Discount::Percent(value),
+        _ => Discount::Fixed(Money::from_cents(value)),
+    };
+
+    Coupon {
+        code,
+        discount,
+        min_order: Money::from_cents(min_order),
+        expires_on,
+        max_uses,
+        max_uses_per_user,
+    }
+}
+
+/// Loads a cart's coupon and checks it against the cart subtotal and past redemptions.
+pub async fn fetch_cart_coupon(
+    pool: &SqlitePool,
+    coupon_id: Option<i64>,
+    user_id: i64,
+    subtotal: Money,
+) -> Result<Option<CartCoupon>, sqlx::Error> {
+    let coupon_id = match coupon_id {
+        Some(coupon_id) => coupon_id,
+        None => return Ok(None),
+    };
+
+    let row = match sqlx::query!("SELECT * FROM coupons WHERE id = ?", coupon_id)
+        .fetch_optional(pool)
+        .await?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let coupon = coupon_from_row(
+        row.code,
+        &row.kind,
+        row.value,
+        row.min_order,
+        row.expires_on,
+        row.max_uses,
+        row.max_uses_per_user,
+    );
+
+    let (uses, user_uses) = fetch_coupon_uses(pool, coupon_id, user_id, None).await?;
+
+    let status = coupon.check(subtotal, chrono::Local::now().date_naive(), uses, user_uses);
+
+    Ok(Some(CartCoupon {
+        id: coupon_id,
+        coupon,
+        status,
+    }))
+}
+
+/// How often a coupon was redeemed by anyone and by `user_id`, leaving out cancelled orders and
+/// `except_order_id`.
+pub async fn fetch_coupon_uses(
+    executor: impl SqliteExecutor<'_>,
+    coupon_id: i64,
+    user_id: i64,
+    except_order_id: Option<i64>,
+) -> Result<(i64, i64), sqlx::Error> {
+    let uses = sqlx::query!(
+        r#"SELECT COUNT(*) AS "uses!: i64", COALESCE(SUM(user_id = ?), 0) AS "user_uses!: i64"
+        FROM orders WHERE coupon_id = ? AND NOT cancelled AND id IS NOT ?"#,
+        user_id,
+        coupon_id,
+        except_order_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok((uses.uses, uses.user_uses))
+}
+
+pub async fn apply_coupon_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: AppDialogue,
+) -> HandlerResult {
+    dialogue.update(State::ReceiveCouponCode).await?;
+
+    bot.send_message(q.chat_id().unwrap(), "Please, send me the coupon code.")
+        .reply_markup(ForceReply::default())
+        .await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+pub async fn receive_coupon_code(
+    bot: Bot,
+    msg: Message,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let code = match msg.text() {
+        Some(code) => code.trim().to_uppercase(),
+        None => {
+            bot.send_message(msg.chat.id, "Please, send me the coupon code.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    tracing::info!("coupon code: {}", code);
+
+    let coupon_id = match sqlx::query!("SELECT id FROM coupons WHERE code = ?", code)
+        .fetch_optional(&pool)
+        .await?
+    {
+        Some(coupon) => coupon.id,
+        None => {
+            bot.send_message(msg.chat.id, "Unknown coupon code.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    let cart = fetch_cart(&pool, Some(msg.chat.id), user_id).await?;
+
+    let candidate = Cart {
+        coupon_id,
+        ..cart.clone()
+    };
+
+    let cart_coupon = match price_cart(&pool, &config, &candidate, user_id)
+        .await?
+        .coupon
+    {
+        Some(cart_coupon) => cart_coupon,
+        None => return Ok(()),
+    };
+
+    dialogue.exit().await?;
+
+    if let Err(err) = cart_coupon.status {
+        bot.send_message(msg.chat.id, err.message(&config.currency))
+            .await?;
+        return Ok(());
+    }
+
+    sqlx::query!(
+        "UPDATE carts SET coupon_id = ? WHERE id = ?",
+        cart_coupon.id,
+        cart.id
+    )
+    .execute(&pool)
+    .await?;
+
+    bot.send_message(
+        msg.chat.id,
+        f!("Coupon {} applied.", cart_coupon.coupon.code),
+    )
+    .await?;
+
+    update_cart_message(&bot, &pool, &config, msg.chat.id, user_id).await?;
+
+    Ok(())
+}
+
+pub async fn remove_coupon_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+    let cart = fetch_cart(&pool, q.chat_id(), user_id).await?;
+
+    sqlx::query!("UPDATE carts SET coupon_id = NULL WHERE id = ?", cart.id)
+        .execute(&pool)
+        .await?;
+
+    refresh_cart_message(&bot, &q, &pool, &config).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
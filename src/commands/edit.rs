@@ -0,0 +1,349 @@
+use crate::commands::gallery::send_gallery_manager;
+use crate::money::Money;
+use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::{assert_admin_id, Config};
+use crate::State;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{
+    prelude::*,
+    types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// A product field that is edited by typing its new value.
+#[derive(Clone, Copy, Debug)]
+pub enum ProductField {
+    Name,
+    Description,
+    Price,
+    Category,
+    MaxQuantity,
+}
+
+impl ProductField {
+    fn prompt(self) -> &'static str {
+        match self {
+            ProductField::Name => "Please, send me the new product name.",
+            ProductField::Description => "Please, send me the new product description.",
+            ProductField::Price => "Please, send me the new product price, e.g. 12.50.",
+            ProductField::Category => "Please, send me the new product category, or - to clear it.",
+            ProductField::MaxQuantity => {
+                "Please, send me the most units a customer can order, or - to remove the limit."
+            }
+        }
+    }
+}
+
+pub async fn edit_product(bot: Bot, msg: Message, dialogue: AppDialogue) -> HandlerResult {
+    tracing::info!("processing /edit command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    bot.send_message(msg.chat.id, "Please, send me the product id.")
+        .reply_markup(ForceReply::default())
+        .await?;
+
+    dialogue.update(State::ReceiveEditProductId).await?;
+
+    Ok(())
+}
+
+pub async fn receive_edit_product_id(
+    bot: Bot,
+    dialogue: AppDialogue,
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let product_id = match msg.text().map(|text| text.parse::<i64>()) {
+        Some(Ok(product_id)) => product_id,
+        _ => {
+            bot.send_message(msg.chat.id, "Invalid product id.").await?;
+            return Ok(());
+        }
+    };
+
+    if sqlx::query!("SELECT id FROM products WHERE id = ?", product_id)
+        .fetch_optional(&pool)
+        .await?
+        .is_none()
+    {
+        bot.send_message(msg.chat.id, "Invalid product id.").await?;
+        return Ok(());
+    }
+
+    dialogue.exit().await?;
+
+    send_edit_menu(&bot, msg.chat.id, &pool, &config, product_id).await
+}
+
+pub async fn edit_product_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    config: Config,
+    field: &str,
+    product_id: i64,
+) -> HandlerResult {
+    let admin_id = q.from.id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    let field = match field {
+        "name" => ProductField::Name,
+        "description" => ProductField::Description,
+        "price" => ProductField::Price,
+        "category" => ProductField::Category,
+        "max_quantity" => ProductField::MaxQuantity,
+        "image" => {
+            send_gallery_manager(&bot, chat_id, &pool, product_id, 0).await?;
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        "visible" | "in_stock" => {
+            let value = if field == "visible" {
+                sqlx::query!(
+                    "UPDATE products SET visible = NOT visible WHERE id = ? RETURNING visible",
+                    product_id
+                )
+                .fetch_one(&pool)
+                .await?
+                .visible
+            } else {
+                sqlx::query!(
+                    "UPDATE products SET in_stock = NOT in_stock WHERE id = ? RETURNING in_stock",
+                    product_id
+                )
+                .fetch_one(&pool)
+                .await?
+                .in_stock
+            };
+
+            tracing::info!(
+                "admin {} set product {} {} to {}",
+                admin_id,
+                product_id,
+                field,
+                value
+            );
+
+            if let Some(message) = q.message {
+                bot.delete_message(chat_id, message.id).await?;
+            }
+            send_edit_menu(&bot, chat_id, &pool, &config, product_id).await?;
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        _ => {
+            if let Some(message) = q.message {
+                bot.delete_message(chat_id, message.id).await?;
+            }
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+    };
+
+    dialogue
+        .update(State::ReceiveEditProductField { product_id, field })
+        .await?;
+
+    bot.send_message(chat_id, field.prompt())
+        .reply_markup(ForceReply::default())
+        .await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+pub async fn receive_edit_product_field(
+    bot: Bot,
+    dialogue: AppDialogue,
+    (product_id, field): (i64, ProductField),
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    let value = match msg.text().map(ToOwned::to_owned) {
+        Some(value) => value,
+        None => {
+            bot.send_message(msg.chat.id, field.prompt())
+                .reply_markup(ForceReply::default())
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let product = sqlx::query!("SELECT * FROM products WHERE id = ?", product_id)
+        .fetch_one(&pool)
+        .await?;
+
+    let (old, new) = match field {
+        ProductField::Name => {
+            sqlx::query!(
+                "UPDATE products SET name = ? WHERE id = ?",
+                value,
+                product_id
+            )
+            .execute(&pool)
+            .await?;
+
+            (product.name, value)
+        }
+        ProductField::Description => {
+            sqlx::query!(
+                "UPDATE products SET description = ? WHERE id = ?",
+                value,
+                product_id
+            )
+            .execute(&pool)
+            .await?;
+
+            (product.description, value)
+        }
+        ProductField::Price => {
+            let price = match Money::parse(&value, &config.currency) {
+                Ok(price) => price,
+                Err(_) => {
+                    bot.send_message(msg.chat.id, "Invalid price. Try again.")
+                        .await?;
+                    return Ok(());
+                }
+            };
+
+            let cents = price.cents();
+            sqlx::query!(
+                "UPDATE products SET price = ? WHERE id = ?",
+                cents,
+                product_id
+            )
+            .execute(&pool)
+            .await?;
+
+            (
+                Money::from_cents(product.price).to_decimal_string(),
+                price.to_decimal_string(),
+            )
+        }
+        ProductField::Category => {
+            let category = Some(value.trim()).filter(|category| *category != "-");
+
+            sqlx::query!(
+                "UPDATE products SET category = ? WHERE id = ?",
+                category,
+                product_id
+            )
+            .execute(&pool)
+            .await?;
+
+            (
+                product.category.unwrap_or_default(),
+                category.unwrap_or_default().to_owned(),
+            )
+        }
+        ProductField::MaxQuantity => {
+            let max_quantity = match value.trim() {
+                "-" => None,
+                value => match value.parse::<i64>() {
+                    Ok(max_quantity) if max_quantity > 0 => Some(max_quantity),
+                    _ => {
+                        bot.send_message(msg.chat.id, "Invalid quantity. Try again.")
+                            .await?;
+                        return Ok(());
+                    }
+                },
+            };
+
+            sqlx::query!(
+                "UPDATE products SET max_quantity = ? WHERE id = ?",
+                max_quantity,
+                product_id
+            )
+            .execute(&pool)
+            .await?;
+
+            (
+                product
+                    .max_quantity
+                    .map(|max| max.to_string())
+                    .unwrap_or_default(),
+                max_quantity.map(|max| max.to_string()).unwrap_or_default(),
+            )
+        }
+    };
+
+    tracing::info!(
+        "admin {} changed product {} {:?} from {:?} to {:?}",
+        msg.from().map(|from| from.id.0).unwrap_or_default(),
+        product_id,
+        field,
+        old,
+        new
+    );
+
+    dialogue.exit().await?;
+
+    send_edit_menu(&bot, msg.chat.id, &pool, &config, product_id).await
+}
+
+/// Sends the product's current values with a button for each editable field.
+async fn send_edit_menu(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    config: &Config,
+    product_id: i64,
+) -> HandlerResult {
+    let product = sqlx::query!("SELECT * FROM products WHERE id = ?", product_id)
+        .fetch_one(pool)
+        .await?;
+
+    let button = |text: &str, field: &str| {
+        InlineKeyboardButton::callback(text, f!("edit_product {field} {product_id}"))
+    };
+
+    bot.send_message(
+        chat_id,
+        f!(
+            "Editing product #{product_id}:\n\nName: {}\n\nDescription: {}\n\nPrice: {}\n\nCategory: {}\n\nMax quantity: {}\n\nIn stock: {}\n\nVisible: {}",
+            product.name,
+            product.description,
+            Money::from_cents(product.price).format(&config.currency),
+            product.category.as_deref().unwrap_or("none"),
+            product
+                .max_quantity
+                .map(|max| max.to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+            if product.in_stock { "yes" } else { "no" },
+            if product.visible { "yes" } else { "no" }
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([
+        vec![button("Name", "name"), button("Description", "description")],
+        vec![button("Price", "price"), button("Category", "category")],
+        vec![button("Max Quantity", "max_quantity"), button("Image", "image")],
+        vec![
+            button(
+                if product.in_stock { "Sold Out" } else { "In Stock" },
+                "in_stock",
+            ),
+            button(if product.visible { "Hide" } else { "Show" }, "visible"),
+        ],
+        vec![button("Done", "done")],
+    ]))
+    .await?;
+
+    Ok(())
+}
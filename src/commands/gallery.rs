@@ -0,0 +1,301 @@
+use crate::commands::inventory::{fetch_product_images, gallery_row};
+use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::{assert_admin_id, product_photo, upload_image_url};
+use crate::State;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{
+    prelude::*,
+    types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, InputMedia, InputMediaPhoto},
+    ApiError, RequestError,
+};
+
+pub async fn gallery(bot: Bot, msg: Message, dialogue: AppDialogue) -> HandlerResult {
+    tracing::info!("processing /gallery command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    bot.send_message(msg.chat.id, "Please, send me the product id.")
+        .reply_markup(ForceReply::default())
+        .await?;
+
+    dialogue.update(State::ReceiveGalleryProductId).await?;
+
+    Ok(())
+}
+
+pub async fn receive_gallery_product_id(
+    bot: Bot,
+    dialogue: AppDialogue,
+    msg: Message,
+    pool: SqlitePool,
+) -> HandlerResult {
+    let product_id = match msg.text().map(|text| text.parse::<i64>()) {
+        Some(Ok(product_id)) => product_id,
+        _ => {
+            bot.send_message(msg.chat.id, "Invalid product id.").await?;
+            return Ok(());
+        }
+    };
+
+    if sqlx::query!("SELECT id FROM products WHERE id = ?", product_id)
+        .fetch_optional(&pool)
+        .await?
+        .is_none()
+    {
+        bot.send_message(msg.chat.id, "Invalid product id.").await?;
+        return Ok(());
+    }
+
+    dialogue.exit().await?;
+
+    send_gallery_manager(&bot, msg.chat.id, &pool, product_id, 0).await
+}
+
+/// Adds every photo or image URL sent while in this state to the end of the gallery.
+pub async fn receive_gallery_image(
+    bot: Bot,
+    msg: Message,
+    product_id: i64,
+    pool: SqlitePool,
+) -> HandlerResult {
+    let image = match (msg.photo(), msg.text()) {
+        (Some(photos), _) => photos.last().map(|photo| photo.file.id.clone()),
+        (None, Some(url)) => upload_image_url(&bot, msg.chat.id, url).await,
+        (None, None) => None,
+    };
+
+    let image = match image {
+        Some(image) => image,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me the image as a photo or an image URL.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO product_images (product_id, file_id, position)
+        VALUES (?, ?, (SELECT COALESCE(MAX(position) + 1, 0) FROM product_images WHERE product_id = ?))",
+        product_id,
+        image,
+        product_id
+    )
+    .execute(&pool)
+    .await?;
+
+    let count = fetch_product_images(&pool, product_id).await?.len();
+
+    bot.send_message(
+        msg.chat.id,
+        f!("Image added ({count} in the gallery). Send another one, or tap Done."),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([vec![
+        InlineKeyboardButton::callback("Done", f!("gallery manage {product_id} {}", count - 1)),
+    ]]))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn gallery_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    action: &str,
+    product_id: i64,
+    index: usize,
+) -> HandlerResult {
+    assert_admin_id(q.from.id.to_string().parse::<i64>()?)?;
+
+    let (chat_id, message_id) = match (q.chat_id(), &q.message) {
+        (Some(chat_id), Some(message)) => (chat_id, message.id),
+        _ => return Ok(()),
+    };
+
+    let images = sqlx::query!(
+        "SELECT id, position FROM product_images WHERE product_id = ? ORDER BY position, id",
+        product_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let index = match action {
+        "show" => index,
+        "up" | "down" => {
+            let other = match action {
+                "up" => index.checked_sub(1),
+                _ => Some(index + 1).filter(|other| *other < images.len()),
+            };
+
+            match (images.get(index), other.and_then(|other| images.get(other))) {
+                (Some(image), Some(swapped)) => {
+                    sqlx::query!(
+                        "UPDATE product_images SET position = ? WHERE id = ?",
+                        swapped.position,
+                        image.id
+                    )
+                    .execute(&pool)
+                    .await?;
+
+                    sqlx::query!(
+                        "UPDATE product_images SET position = ? WHERE id = ?",
+                        image.position,
+                        swapped.id
+                    )
+                    .execute(&pool)
+                    .await?;
+
+                    other.unwrap_or(index)
+                }
+                _ => {
+                    bot.answer_callback_query(q.id)
+                        .text("The image can't be moved any further.")
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+        "remove" => {
+            if images.len() <= 1 {
+                bot.answer_callback_query(q.id)
+                    .text("A product needs at least one image.")
+                    .await?;
+                return Ok(());
+            }
+
+            if let Some(image) = images.get(index) {
+                sqlx::query!("DELETE FROM product_images WHERE id = ?", image.id)
+                    .execute(&pool)
+                    .await?;
+            }
+
+            index.min(images.len() - 2)
+        }
+        "add" => {
+            dialogue
+                .update(State::ReceiveGalleryImage { product_id })
+                .await?;
+
+            bot.send_message(
+                chat_id,
+                "Please, send me the images as photos or image URLs.",
+            )
+            .await?;
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        "manage" => {
+            dialogue.exit().await?;
+
+            bot.delete_message(chat_id, message_id).await?;
+            send_gallery_manager(&bot, chat_id, &pool, product_id, index).await?;
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+        _ => {
+            bot.delete_message(chat_id, message_id).await?;
+
+            bot.answer_callback_query(q.id).await?;
+            return Ok(());
+        }
+    };
+
+    let images = fetch_product_images(&pool, product_id).await?;
+
+    if let Some(image) = images.get(index) {
+        let media = InputMedia::Photo(
+            InputMediaPhoto::new(product_photo(image)).caption(gallery_caption(
+                product_id,
+                index,
+                images.len(),
+            )),
+        );
+
+        match bot
+            .edit_message_media(chat_id, message_id, media)
+            .reply_markup(gallery_keyboard(product_id, index, images.len()))
+            .await
+        {
+            // The "i/n" button shows the image that is already there.
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Sends the gallery manager, showing one image at a time with controls to reorder it.
+pub async fn send_gallery_manager(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    product_id: i64,
+    index: usize,
+) -> HandlerResult {
+    let images = fetch_product_images(pool, product_id).await?;
+    let index = index.min(images.len().saturating_sub(1));
+
+    match images.get(index) {
+        Some(image) => {
+            bot.send_photo(chat_id, product_photo(image))
+                .caption(gallery_caption(product_id, index, images.len()))
+                .reply_markup(gallery_keyboard(product_id, index, images.len()))
+                .await?;
+        }
+        None => {
+            bot.send_message(chat_id, f!("Product #{product_id} has no images yet."))
+                .reply_markup(InlineKeyboardMarkup::new([vec![
+                    InlineKeyboardButton::callback("Add images", f!("gallery add {product_id} 0")),
+                    InlineKeyboardButton::callback("Done", f!("gallery done {product_id} 0")),
+                ]]))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn gallery_caption(product_id: i64, index: usize, count: usize) -> String {
+    f!(
+        "Gallery of product #{product_id}: image {} of {count}.",
+        index + 1
+    )
+}
+
+fn gallery_keyboard(product_id: i64, index: usize, count: usize) -> InlineKeyboardMarkup {
+    let action = |text: &str, action: &str| {
+        InlineKeyboardButton::callback(text, f!("gallery {action} {product_id} {index}"))
+    };
+
+    let mut rows = vec![];
+
+    if count > 1 {
+        rows.push(gallery_row("gallery show", product_id, index, count));
+        rows.push(vec![
+            action("Move earlier", "up"),
+            action("Move later", "down"),
+        ]);
+    }
+
+    rows.push(vec![
+        action("Remove", "remove"),
+        action("Add images", "add"),
+    ]);
+    rows.push(vec![action("Done", "done")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
@@ -1,11 +1,12 @@
 use teloxide::{prelude::*, utils::command::BotCommands};
 
 use crate::schema::{Command, HandlerResult};
+use crate::utils::delete_command;
 
 pub async fn help(bot: Bot, msg: Message) -> HandlerResult {
     tracing::info!("processing /help command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
     bot.send_message(msg.chat.id, Command::descriptions().to_string())
         .await?;
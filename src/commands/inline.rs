@@ -0,0 +1,91 @@
+use crate::money::Money;
+use crate::schema::HandlerResult;
+use crate::utils::Config;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::{
+    prelude::*,
+    types::{
+        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle,
+        InlineQueryResultCachedPhoto, InlineQueryResultPhoto, InputMessageContent,
+        InputMessageContentText, Me,
+    },
+};
+use url::Url;
+
+pub async fn inline_query(
+    bot: Bot,
+    q: InlineQuery,
+    me: Me,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
+    tracing::info!("processing inline query {:?} from {}", q.query, q.from.id);
+
+    let pattern = f!("%{}%", q.query.trim());
+
+    // Telegram accepts at most 50 results per answer.
+    let products = sqlx::query!(
+        r#"SELECT products.id AS "id!", products.name, products.description, products.price,
+            (SELECT file_id FROM product_images WHERE product_id = products.id
+                ORDER BY position, id LIMIT 1) AS "image?: String"
+        FROM products WHERE visible AND NOT archived AND (name LIKE ? OR description LIKE ?)
+        ORDER BY name LIMIT 50"#,
+        pattern,
+        pattern
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let results = products
+        .into_iter()
+        .map(|product| {
+            let id = product.id.to_string();
+            let price = Money::from_cents(product.price).format(&config.currency);
+            let caption = f!(
+                "{}\n\n{}\n\nPrice: {}",
+                product.name,
+                product.description,
+                price
+            );
+
+            let deep_link = f!("{}?start=product_{}", me.tme_url(), product.id);
+            let keyboard = InlineKeyboardMarkup::new([vec![InlineKeyboardButton::url(
+                "Open in bot",
+                Url::parse(&deep_link).expect("deep link is a valid url"),
+            )]]);
+
+            match product.image.map(|image| (Url::parse(&image), image)) {
+                Some((Ok(image), _)) => InlineQueryResult::Photo(
+                    InlineQueryResultPhoto::new(id, image.clone(), image)
+                        .title(product.name)
+                        .description(price.clone())
+                        .caption(caption)
+                        .reply_markup(keyboard),
+                ),
+                Some((Err(_), image)) => InlineQueryResult::CachedPhoto(
+                    InlineQueryResultCachedPhoto::new(id, image)
+                        .title(product.name)
+                        .description(price.clone())
+                        .caption(caption)
+                        .reply_markup(keyboard),
+                ),
+                None => InlineQueryResult::Article(
+                    InlineQueryResultArticle::new(
+                        id,
+                        product.name,
+                        InputMessageContent::Text(InputMessageContentText::new(caption)),
+                    )
+                    .description(price.clone())
+                    .reply_markup(keyboard),
+                ),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    bot.answer_inline_query(q.id, results)
+        .cache_time(30)
+        .await?;
+
+    Ok(())
+}
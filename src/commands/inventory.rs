@@ -1,20 +1,25 @@
+use crate::commands::cart::{check_cart_quantity, fetch_cart, update_cart_message};
+use crate::commands::moderation::{is_restricted, RESTRICTED};
+use crate::commands::wishlist::is_saved;
+use crate::money::Money;
 use crate::schema::HandlerResult;
-use crate::utils::format_price;
+use crate::utils::{delete_command, product_photo, Config};
 use format as f;
 use itertools::Itertools;
 use sqlx::SqlitePool;
 use teloxide::dispatching::dialogue::GetChatId;
 use teloxide::{
     prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputMedia, InputMediaPhoto},
+    ApiError, RequestError,
 };
 
 pub async fn inventory(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
     tracing::info!("processing /inventory command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
-    let products = sqlx::query!("SELECT * FROM products")
+    let products = sqlx::query!("SELECT * FROM products WHERE visible AND NOT archived")
         .fetch_all(&pool)
         .await?;
 
@@ -44,6 +49,7 @@ pub async fn view_product_callback(
     bot: Bot,
     q: CallbackQuery,
     pool: SqlitePool,
+    config: Config,
     product_id: i64,
 ) -> HandlerResult {
     let chat_id = match q.chat_id() {
@@ -57,58 +63,273 @@ pub async fn view_product_callback(
         }
     };
 
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    send_product(&bot, chat_id, &pool, &config, product_id, user_id).await?;
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Sends the product card with its cart and wishlist controls for `user_id` to the given chat.
+pub async fn send_product(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    config: &Config,
+    product_id: i64,
+    user_id: i64,
+) -> HandlerResult {
     let product = sqlx::query!("SELECT * FROM products WHERE id = ?", product_id)
-        .fetch_one(&pool)
+        .fetch_one(pool)
         .await?;
 
-    let (name, description, price, image) = (
-        product.name,
-        product.description,
-        product.price,
-        product.image,
+    if product.archived || !product.visible {
+        bot.send_message(chat_id, "This product is no longer available.")
+            .await?;
+        return Ok(());
+    }
+
+    let images = fetch_product_images(pool, product_id).await?;
+    let saved = is_saved(pool, user_id, product_id).await?;
+
+    let caption = product_caption(
+        product_id,
+        &product.name,
+        &product.description,
+        Money::from_cents(product.price).format(&config.currency),
+        product.in_stock,
     );
+    let keyboard = product_keyboard(product_id, 0, images.len(), product.in_stock, saved);
 
-    bot.send_message(
-            chat_id,
-            f!("Name: {name}\n\nID: {product_id}\n\nDescription: {description}\n\nPrice: {}\n\nImage: {image}", format_price(price)),
-        )
-        .reply_markup(InlineKeyboardMarkup::new([
-            vec![InlineKeyboardButton::callback(
-                "Add to cart",
-                f!("add_to_cart {product_id}"),
-            ),
-            InlineKeyboardButton::callback(
-                "Back",
-                "back",
-            )
-         ],
-        ]))
+    match images.first() {
+        Some(image) => {
+            bot.send_photo(chat_id, product_photo(image))
+                .caption(caption)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        None => {
+            bot.send_message(chat_id, caption)
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Swipes the product card to another image of its gallery by editing the photo in place.
+pub async fn view_product_image_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+    product_id: i64,
+    index: usize,
+) -> HandlerResult {
+    let (chat_id, message_id) = match (q.chat_id(), &q.message) {
+        (Some(chat_id), Some(message)) => (chat_id, message.id),
+        _ => return Ok(()),
+    };
+
+    let product = sqlx::query!("SELECT * FROM products WHERE id = ?", product_id)
+        .fetch_one(&pool)
         .await?;
 
+    let images = fetch_product_images(&pool, product_id).await?;
+    let saved = is_saved(&pool, q.from.id.to_string().parse::<i64>()?, product_id).await?;
+
+    if let Some(image) = images.get(index) {
+        let media = InputMedia::Photo(InputMediaPhoto::new(product_photo(image)).caption(
+            product_caption(
+                product_id,
+                &product.name,
+                &product.description,
+                Money::from_cents(product.price).format(&config.currency),
+                product.in_stock,
+            ),
+        ));
+
+        match bot
+            .edit_message_media(chat_id, message_id, media)
+            .reply_markup(product_keyboard(
+                product_id,
+                index,
+                images.len(),
+                product.in_stock,
+                saved,
+            ))
+            .await
+        {
+            // The "i/n" button shows the image that is already there.
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
     bot.answer_callback_query(q.id).await?;
 
     Ok(())
 }
 
+/// Returns the product's gallery as an ordered list of images, cover first.
+pub async fn fetch_product_images(
+    pool: &SqlitePool,
+    product_id: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let images = sqlx::query!(
+        "SELECT file_id FROM product_images WHERE product_id = ? ORDER BY position, id",
+        product_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(images.into_iter().map(|image| image.file_id).collect())
+}
+
+fn product_caption(
+    product_id: i64,
+    name: &str,
+    description: &str,
+    price: String,
+    in_stock: bool,
+) -> String {
+    let caption = f!(
+        "Name: {name}\n\nID: {product_id}\n\nDescription: {description}\n\nPrice: {}",
+        price
+    );
+
+    if in_stock {
+        caption
+    } else {
+        f!("{caption}\n\nSold out. Save it to be told when it's back.")
+    }
+}
+
+/// Builds the `◀ i/n ▶` row used to swipe through a gallery; arrows wrap around.
+pub fn gallery_row(
+    callback: &str,
+    product_id: i64,
+    index: usize,
+    count: usize,
+) -> Vec<InlineKeyboardButton> {
+    let previous = (index + count - 1) % count;
+    let next = (index + 1) % count;
+
+    vec![
+        InlineKeyboardButton::callback("◀", f!("{callback} {product_id} {previous}")),
+        InlineKeyboardButton::callback(
+            f!("{}/{}", index + 1, count),
+            f!("{callback} {product_id} {index}"),
+        ),
+        InlineKeyboardButton::callback("▶", f!("{callback} {product_id} {next}")),
+    ]
+}
+
+/// Builds the product card keyboard, with gallery arrows when there is more than one image.
+pub fn product_keyboard(
+    product_id: i64,
+    index: usize,
+    count: usize,
+    in_stock: bool,
+    saved: bool,
+) -> InlineKeyboardMarkup {
+    let mut rows = vec![];
+
+    if count > 1 {
+        rows.push(gallery_row("product_image", product_id, index, count));
+    }
+
+    let mut row = vec![];
+    if in_stock {
+        row.push(InlineKeyboardButton::callback(
+            "Add to cart",
+            f!("add_to_cart {product_id}"),
+        ));
+    }
+    row.push(InlineKeyboardButton::callback(
+        if saved { "♥ Saved" } else { "♡ Save" },
+        f!("save_product {product_id} {index}"),
+    ));
+    row.push(InlineKeyboardButton::callback("Back", "back"));
+    rows.push(row);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
 pub async fn add_to_cart_callback(
     bot: Bot,
     q: CallbackQuery,
     product_id: i64,
     pool: SqlitePool,
+    config: Config,
 ) -> HandlerResult {
     let user_id = q.from.id.to_string().parse::<i64>()?;
 
-    let cart = sqlx::query!("SELECT * FROM carts WHERE user_id = ?", user_id)
-        .fetch_one(&pool)
-        .await?;
+    if is_restricted(&pool, user_id).await? {
+        bot.answer_callback_query(q.id)
+            .text(RESTRICTED)
+            .show_alert(true)
+            .await?;
+        return Ok(());
+    }
+
+    let product = sqlx::query!(
+        "SELECT visible, archived, in_stock, price FROM products WHERE id = ?",
+        product_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let price = match product {
+        Some(product) if product.visible && !product.archived => {
+            if !product.in_stock {
+                bot.answer_callback_query(q.id)
+                    .text("This product is sold out.")
+                    .await?;
+                return Ok(());
+            }
+
+            product.price
+        }
+        _ => {
+            bot.answer_callback_query(q.id)
+                .text("This product is not available.")
+                .await?;
+            return Ok(());
+        }
+    };
 
+    let chat_id = q.chat_id();
+    let cart = fetch_cart(&pool, chat_id, user_id).await?;
+
+    // In a shared cart every member adds to their own item, so the cart shows who added what.
     let cart_item = sqlx::query!(
-        "SELECT * FROM cart_items WHERE cart_id = ? AND product_id = ?",
+        "SELECT * FROM cart_items WHERE cart_id = ? AND product_id = ? AND added_by = ?",
         cart.id,
-        product_id
+        product_id,
+        user_id
     );
 
-    match cart_item.fetch_optional(&pool).await {
+    let cart_item = cart_item.fetch_optional(&pool).await;
+
+    let (cart_item_id, quantity) = match &cart_item {
+        Ok(Some(cart_item)) => (Some(cart_item.id), cart_item.quantity + 1),
+        _ => (None, 1),
+    };
+
+    if let Err(err) =
+        check_cart_quantity(&pool, &config, cart.id, product_id, cart_item_id, quantity).await?
+    {
+        bot.answer_callback_query(q.id)
+            .text(err.to_string())
+            .await?;
+        return Ok(());
+    }
+
+    match cart_item {
         Ok(cart_item) => match cart_item {
             Some(cart_item) => {
                 sqlx::query!(
@@ -124,10 +345,13 @@ pub async fn add_to_cart_callback(
             }
             None => {
                 sqlx::query!(
-                    "INSERT INTO cart_items (cart_id, product_id, quantity) VALUES (?, ?, ?)",
+                    "INSERT INTO cart_items (cart_id, product_id, quantity, added_by, price)
+                    VALUES (?, ?, ?, ?, ?)",
                     cart.id,
                     product_id,
-                    1
+                    1,
+                    user_id,
+                    price
                 )
                 .execute(&pool)
                 .await?;
@@ -141,8 +365,13 @@ pub async fn add_to_cart_callback(
             bot.answer_callback_query(q.id)
                 .text("Failed to add product to cart.")
                 .await?;
+            return Ok(());
         }
     }
 
+    if let Some(chat_id) = chat_id {
+        update_cart_message(&bot, &pool, &config, chat_id, user_id).await?;
+    }
+
     Ok(())
 }
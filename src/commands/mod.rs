@@ -1,9 +1,19 @@
 pub mod add;
 pub mod cancel;
 pub mod cart;
+pub mod catalog;
+pub mod coupons;
+pub mod edit;
+pub mod gallery;
 pub mod help;
+pub mod inline;
 pub mod inventory;
+pub mod moderation;
 pub mod orders;
+pub mod points;
+pub mod profile;
+pub mod reminders;
 pub mod remove;
 pub mod shop;
 pub mod start;
+pub mod wishlist;
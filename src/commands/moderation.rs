@@ -0,0 +1,261 @@
+use crate::moderation::{parse_user_ref, status_notice, UserRef, UserStatus};
+use crate::schema::HandlerResult;
+use crate::utils::assert_admin_id;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+
+/// Message shown to restricted customers when they try to order.
+pub const RESTRICTED: &str =
+    "Your account is restricted and can't place orders. Please, contact the store.";
+
+pub async fn ban(bot: Bot, msg: Message, pool: SqlitePool, args: String) -> HandlerResult {
+    tracing::info!("processing /ban command in chat {}", msg.chat.id);
+
+    change_status(bot, msg, pool, args, UserStatus::Banned).await
+}
+
+pub async fn restrict(bot: Bot, msg: Message, pool: SqlitePool, args: String) -> HandlerResult {
+    tracing::info!("processing /restrict command in chat {}", msg.chat.id);
+
+    change_status(bot, msg, pool, args, UserStatus::Restricted).await
+}
+
+pub async fn unban(bot: Bot, msg: Message, pool: SqlitePool, args: String) -> HandlerResult {
+    tracing::info!("processing /unban command in chat {}", msg.chat.id);
+
+    change_status(bot, msg, pool, args, UserStatus::Active).await
+}
+
+/// Sets the status of the customer named in `args` and records the change in the audit trail.
+/// Lists the banned and restricted customers without arguments.
+async fn change_status(
+    bot: Bot,
+    msg: Message,
+    pool: SqlitePool,
+    args: String,
+    status: UserStatus,
+) -> HandlerResult {
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let admin_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    if args.trim().is_empty() {
+        return list_moderated_users(&bot, msg.chat.id, &pool).await;
+    }
+
+    let (user_ref, reason) = match parse_user_ref(&args) {
+        Some(parsed) => parsed,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me a user id or @username, e.g. /ban @username spamming.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let (id, username) = match &user_ref {
+        UserRef::Id(id) => (Some(*id), None),
+        UserRef::Username(username) => (None, Some(username.as_str())),
+    };
+
+    let mut users = sqlx::query!(
+        r#"SELECT id AS "id!", first_name, status FROM users
+        WHERE id = ? OR username = ? COLLATE NOCASE ORDER BY id"#,
+        id,
+        username
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    // A username can be left on the row of a user who has since changed it.
+    if users.len() > 1 {
+        let ids = users
+            .iter()
+            .map(|user| user.id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        bot.send_message(
+            msg.chat.id,
+            f!("Several users have been {user_ref} (ids {ids}). Please, use the id instead."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let user = match users.pop() {
+        Some(user) => user,
+        None => {
+            bot.send_message(
+                msg.chat.id,
+                f!("User {user_ref} not found. They must have used the bot before."),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    if assert_admin_id(user.id).is_ok() {
+        bot.send_message(msg.chat.id, "The admin can't be banned or restricted.")
+            .await?;
+        return Ok(());
+    }
+
+    if UserStatus::from_db(&user.status) == status {
+        bot.send_message(
+            msg.chat.id,
+            f!("{} is already {}.", user.first_name, status.as_str()),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let status_str = status.as_str();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET status = ? WHERE id = ?",
+        status_str,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO user_status_changes (user_id, status, reason, changed_by)
+        VALUES (?, ?, ?, ?)",
+        user.id,
+        status_str,
+        reason,
+        admin_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    tracing::info!("admin {} set user {} to {}", admin_id, user.id, status_str);
+
+    // Customers who never started a private chat can't be told.
+    if let Err(err) = bot
+        .send_message(ChatId(user.id), status_notice(status, reason.as_deref()))
+        .await
+    {
+        tracing::warn!("Failed to notify user {} of their status: {}", user.id, err);
+    }
+
+    bot.send_message(
+        msg.chat.id,
+        f!("{} ({user_ref}) is now {status_str}.", user.first_name),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn list_moderated_users(bot: &Bot, chat_id: ChatId, pool: &SqlitePool) -> HandlerResult {
+    let users = sqlx::query!(
+        r#"SELECT id AS "id!", first_name, username, status FROM users
+        WHERE status != 'active' ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let changes = sqlx::query!(
+        r#"SELECT user_status_changes.status, user_status_changes.reason,
+            user_status_changes.created_at AS "created_at!: chrono::NaiveDateTime",
+            users.first_name
+        FROM user_status_changes
+        INNER JOIN users ON user_status_changes.user_id = users.id
+        ORDER BY user_status_changes.id DESC LIMIT 10"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let users = if users.is_empty() {
+        "none".to_owned()
+    } else {
+        users
+            .iter()
+            .map(|user| {
+                f!(
+                    "\n{} - {}{} - {}",
+                    user.id,
+                    user.first_name,
+                    user.username
+                        .as_ref()
+                        .map(|username| f!(" (@{username})"))
+                        .unwrap_or_default(),
+                    user.status
+                )
+            })
+            .collect::<String>()
+    };
+
+    let changes = if changes.is_empty() {
+        "none".to_owned()
+    } else {
+        changes
+            .iter()
+            .map(|change| {
+                f!(
+                    "\n{} - {} set to {}{}",
+                    change.created_at.format("%Y-%m-%d %H:%M"),
+                    change.first_name,
+                    change.status,
+                    change
+                        .reason
+                        .as_ref()
+                        .map(|reason| f!(": {reason}"))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<String>()
+    };
+
+    bot.send_message(
+        chat_id,
+        f!("Banned and restricted users: {users}\n\nRecent changes: {changes}"),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn fetch_user_status(pool: &SqlitePool, user_id: i64) -> Result<UserStatus, sqlx::Error> {
+    let user = sqlx::query!("SELECT status FROM users WHERE id = ?", user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user.map_or(UserStatus::Active, |user| UserStatus::from_db(&user.status)))
+}
+
+/// Whether the user is not allowed to order.
+pub async fn is_restricted(pool: &SqlitePool, user_id: i64) -> Result<bool, sqlx::Error> {
+    Ok(fetch_user_status(pool, user_id).await? != UserStatus::Active)
+}
+
+/// Stops updates from banned users before any handler runs.
+pub async fn is_not_banned(update: Update, pool: SqlitePool) -> bool {
+    let user_id = match update.user() {
+        Some(user) => user.id.0 as i64,
+        None => return true,
+    };
+
+    match fetch_user_status(&pool, user_id).await {
+        Ok(status) => status != UserStatus::Banned,
+        Err(err) => {
+            tracing::error!(
+                "Failed to check whether user {} is banned: {}",
+                user_id,
+                err
+            );
+            true
+        }
+    }
+}
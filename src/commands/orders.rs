@@ -1,12 +1,28 @@
+use crate::commands::cart::format_summary;
+use crate::commands::points::{record_points, reverse_points};
+use crate::loyalty::PointsReason;
+use crate::money::Money;
+use crate::pricing::{split_total, Summary};
 use crate::schema::HandlerResult;
+use crate::utils::{admin_id, assert_admin_id, delete_command, Config};
 use format as f;
+use itertools::Itertools;
 use sqlx::SqlitePool;
-use teloxide::prelude::*;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
 
-pub async fn view_orders(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+pub async fn view_orders(
+    bot: Bot,
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+) -> HandlerResult {
     tracing::info!("processing /orders command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
     let user_id = match msg.from() {
         Some(user) => user.id.to_string().parse::<i64>()?,
@@ -29,14 +45,510 @@ pub async fn view_orders(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerRes
         return Ok(());
     }
 
+    let buttons = orders
+        .iter()
+        .map(|order| {
+            InlineKeyboardButton::callback(f!("#{}", order.id), f!("view_order {}", order.id))
+        })
+        .chunks(4)
+        .into_iter()
+        .map(|chunk| chunk.collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
     let orders = orders
         .iter()
-        .map(|order| format!("#{}", order.id,))
+        .map(|order| {
+            f!(
+                "#{} - {} - {}",
+                order.id,
+                order
+                    .created_at
+                    .map(|created_at| created_at.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+                Money::from_cents(order.total).format(&config.currency_for(&order.currency))
+            )
+        })
         .collect::<Vec<_>>()
         .join("\n\n");
 
-    bot.send_message(msg.chat.id, f!("Your orders:\n\n{}", orders))
+    bot.send_message(
+        msg.chat.id,
+        f!(
+            "Your orders:\n\n{}\n\nSelect an order to view its details:",
+            orders
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new(buttons))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn view_order_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+    order_id: i64,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    let order = sqlx::query!("SELECT user_id, chat_id FROM orders WHERE id = ?", order_id)
+        .fetch_optional(&pool)
+        .await?;
+
+    // Orders of a shared cart can be viewed from the group they were placed in.
+    match order {
+        Some(order)
+            if order.user_id == user_id
+                || order.chat_id == Some(chat_id.0)
+                || assert_admin_id(user_id).is_ok() =>
+        {
+            send_order_detail(&bot, chat_id, &pool, &config, order_id).await?;
+            bot.answer_callback_query(q.id).await?;
+        }
+        _ => {
+            bot.answer_callback_query(q.id)
+                .text("Order not found.")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the order's items and totals. Also used as the receipt after checkout. The delivery
+/// address and phone number are only shown in the orderer's and the admin's private chats.
+pub async fn send_order_detail(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    config: &Config,
+    order_id: i64,
+) -> HandlerResult {
+    let order = sqlx::query!("SELECT * FROM orders WHERE id = ?", order_id)
+        .fetch_one(pool)
+        .await?;
+
+    let currency = config.currency_for(&order.currency);
+
+    // Items of a shared order are listed once per product, whoever added them.
+    let items = sqlx::query!(
+        r#"SELECT SUM(order_items.quantity) AS "quantity!: i64", order_items.price AS "price!",
+            products.name AS "name!"
+        FROM order_items
+        INNER JOIN products ON order_items.product_id = products.id
+        WHERE order_items.order_id = ?
+        GROUP BY order_items.product_id, order_items.price
+        ORDER BY MIN(order_items.id)"#,
+        order_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let items = items
+        .iter()
+        .map(|item| {
+            f!(
+                "{} - x{} - {}",
+                item.name,
+                item.quantity,
+                Money::from_cents(item.price)
+                    .checked_mul(item.quantity)
+                    .unwrap_or_default()
+                    .format(&currency)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let summary = Summary {
+        subtotal: Money::from_cents(order.subtotal),
+        promotions: Money::from_cents(order.promotions),
+        discount: Money::from_cents(order.discount),
+        tax: Money::from_cents(order.tax),
+        fee: Money::from_cents(order.delivery_fee),
+        points: Money::from_cents(order.points_discount),
+        total: Money::from_cents(order.total),
+        tax_included: order.tax_included,
+    };
+
+    let mut fulfillment = order
+        .fulfillment_method
+        .map(|method| f!("\n\nFulfillment: {method}"))
+        .unwrap_or_default();
+    let private = chat_id.0 == order.user_id || assert_admin_id(chat_id.0).is_ok();
+    if let Some(address) = order.address.filter(|_| private) {
+        fulfillment.push_str(&f!("\nDeliver to: {address}"));
+    }
+    if let Some(delivery_zone) = order.delivery_zone {
+        fulfillment.push_str(&f!("\nDelivery zone: {delivery_zone}"));
+    }
+    if let Some(phone) = order.phone.filter(|_| private) {
+        fulfillment.push_str(&f!("\nPhone: {phone}"));
+    }
+    if order.cancelled {
+        fulfillment.push_str("\n\nThis order was cancelled.");
+    } else if order.fulfilled == Some(true) {
+        fulfillment.push_str("\n\nThis order was fulfilled.");
+    }
+
+    bot.send_message(
+        chat_id,
+        f!(
+            "Order #{order_id} ({}):\n\n{items}\n\n--------------------------\n\n{}{fulfillment}",
+            order
+                .created_at
+                .map(|created_at| created_at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+            format_summary(&summary, &currency)
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends each member of a shared order their share of the total privately, in proportion to the
+/// items they added.
+pub async fn split_bill_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+    order_id: i64,
+) -> HandlerResult {
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    let order = match sqlx::query!(
+        "SELECT chat_id, currency, total, cancelled FROM orders WHERE id = ?",
+        order_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    {
+        Some(order) if order.chat_id == Some(chat_id.0) => order,
+        _ => {
+            bot.answer_callback_query(q.id)
+                .text("Order not found.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Only the first tap on the button sends the shares, and never for a cancelled order.
+    let claimed = sqlx::query!(
+        "UPDATE orders SET bill_split_at = CURRENT_TIMESTAMP
+        WHERE id = ? AND bill_split_at IS NULL AND NOT cancelled",
+        order_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if claimed.rows_affected() == 0 {
+        bot.answer_callback_query(q.id)
+            .text(if order.cancelled {
+                "This order was cancelled."
+            } else {
+                "The bill was already split."
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let currency = config.currency_for(&order.currency);
+
+    let members = sqlx::query!(
+        r#"SELECT order_items.added_by AS "added_by!", users.first_name AS "first_name?",
+            SUM(order_items.price * order_items.quantity) AS "subtotal!: i64",
+            GROUP_CONCAT(products.name || ' x' || order_items.quantity, ', ') AS "items!: String"
+        FROM order_items
+        INNER JOIN products ON order_items.product_id = products.id
+        LEFT JOIN users ON order_items.added_by = users.id
+        WHERE order_items.order_id = ? AND order_items.added_by IS NOT NULL
+        GROUP BY order_items.added_by
+        ORDER BY MIN(order_items.id)"#,
+        order_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let shares = split_total(
+        Money::from_cents(order.total),
+        &members
+            .iter()
+            .map(|member| Money::from_cents(member.subtotal))
+            .collect::<Vec<_>>(),
+    );
+
+    let group = q
+        .message
+        .as_ref()
+        .and_then(|message| message.chat.title())
+        .unwrap_or("your group");
+
+    // Members who never started a private chat with the bot can't be messaged.
+    let mut unreachable = vec![];
+    for (member, share) in members.iter().zip(shares) {
+        let sent = bot
+            .send_message(
+                ChatId(member.added_by),
+                f!(
+                    "Your share of order #{order_id} from {group} is {}.\n\nYour items: {}",
+                    share.format(&currency),
+                    member.items
+                ),
+            )
+            .await;
+
+        if sent.is_err() {
+            unreachable.push(member.first_name.clone().unwrap_or_default());
+        }
+    }
+
+    if !unreachable.is_empty() {
+        bot.send_message(
+            chat_id,
+            f!(
+                "Couldn't send the bill to {}. They need to start a private chat with me first.",
+                unreachable.join(", ")
+            ),
+        )
+        .await?;
+    }
+
+    bot.answer_callback_query(q.id)
+        .text("Sent each member their share.")
         .await?;
 
     Ok(())
 }
+
+/// Marks an order as fulfilled and credits the customer with the loyalty points it earned.
+pub async fn fulfill_order(
+    bot: Bot,
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+    args: String,
+) -> HandlerResult {
+    tracing::info!("processing /fulfill command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let admin_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    let order_id = match args.trim().trim_start_matches('#').parse::<i64>() {
+        Ok(order_id) => order_id,
+        Err(_) => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me an order id, e.g. /fulfill 12.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let order = sqlx::query!(
+        "SELECT user_id, fulfilled, cancelled, subtotal, promotions, discount, points_discount
+        FROM orders WHERE id = ?",
+        order_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let order = match order {
+        Some(order) if order.cancelled => {
+            bot.send_message(msg.chat.id, f!("Order #{order_id} was cancelled."))
+                .await?;
+            return Ok(());
+        }
+        Some(order) if order.fulfilled == Some(true) => {
+            bot.send_message(msg.chat.id, f!("Order #{order_id} is already fulfilled."))
+                .await?;
+            return Ok(());
+        }
+        Some(order) => order,
+        None => {
+            bot.send_message(msg.chat.id, f!("Order #{order_id} not found."))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+
+    // Another admin may have fulfilled or cancelled the order since it was read.
+    let updated = sqlx::query!(
+        "UPDATE orders SET fulfilled = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ? AND NOT cancelled AND fulfilled IS NOT TRUE",
+        order_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        tx.rollback().await?;
+        bot.send_message(
+            msg.chat.id,
+            f!("Order #{order_id} was already fulfilled or cancelled."),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Points are earned on what was paid for the products, not on tax or fees.
+    let spent = Money::from_cents(
+        order.subtotal - order.promotions - order.discount - order.points_discount,
+    );
+    let earned = config.loyalty.points_earned(spent);
+
+    if earned > 0 {
+        record_points(
+            &mut *tx,
+            order.user_id,
+            order_id,
+            earned,
+            PointsReason::Earned,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    tracing::info!("admin {} fulfilled order {}", admin_id, order_id);
+
+    let notice = if earned > 0 {
+        f!("Your order #{order_id} was fulfilled. You earned {earned} loyalty points!")
+    } else {
+        f!("Your order #{order_id} was fulfilled.")
+    };
+    notify_customer(&bot, order.user_id, notice).await;
+
+    bot.send_message(msg.chat.id, f!("Order #{order_id} marked as fulfilled."))
+        .await?;
+
+    Ok(())
+}
+
+/// Cancels or refunds an order, reversing the loyalty points it earned or redeemed.
+pub async fn cancel_order(bot: Bot, msg: Message, pool: SqlitePool, args: String) -> HandlerResult {
+    tracing::info!("processing /cancel_order command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let admin_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    let order_id = match args.trim().trim_start_matches('#').parse::<i64>() {
+        Ok(order_id) => order_id,
+        Err(_) => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me an order id, e.g. /cancel_order 12.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let order = sqlx::query!(
+        "SELECT user_id, cancelled FROM orders WHERE id = ?",
+        order_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let order = match order {
+        Some(order) if order.cancelled => {
+            bot.send_message(msg.chat.id, f!("Order #{order_id} is already cancelled."))
+                .await?;
+            return Ok(());
+        }
+        Some(order) => order,
+        None => {
+            bot.send_message(msg.chat.id, f!("Order #{order_id} not found."))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+
+    // Another admin may have cancelled the order since it was read.
+    let updated = sqlx::query!(
+        "UPDATE orders SET cancelled = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ? AND NOT cancelled",
+        order_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        tx.rollback().await?;
+        bot.send_message(msg.chat.id, f!("Order #{order_id} is already cancelled."))
+            .await?;
+        return Ok(());
+    }
+
+    let reversed = reverse_points(&mut tx, order_id).await?;
+
+    tx.commit().await?;
+
+    tracing::info!("admin {} cancelled order {}", admin_id, order_id);
+
+    let points = reversed
+        .iter()
+        .find(|(user_id, _)| *user_id == order.user_id)
+        .map_or(0, |(_, points)| *points);
+
+    let notice = match points {
+        0 => f!("Your order #{order_id} was cancelled."),
+        points if points > 0 => f!(
+            "Your order #{order_id} was cancelled. The {points} loyalty points you redeemed on it were returned."
+        ),
+        points => f!(
+            "Your order #{order_id} was cancelled. The {} loyalty points it earned were taken back.",
+            -points
+        ),
+    };
+    notify_customer(&bot, order.user_id, notice).await;
+
+    bot.send_message(msg.chat.id, f!("Order #{order_id} cancelled."))
+        .await?;
+
+    Ok(())
+}
+
+/// Sends the admin the details of a newly placed order, so staff can prepare and deliver it.
+pub async fn notify_staff(bot: &Bot, pool: &SqlitePool, config: &Config, order_id: i64) {
+    let sent = match admin_id() {
+        Ok(admin_id) => send_order_detail(bot, ChatId(admin_id), pool, config, order_id).await,
+        Err(err) => Err(err.into()),
+    };
+
+    if let Err(err) = sent {
+        tracing::warn!("Failed to notify staff about order {}: {}", order_id, err);
+    }
+}
+
+/// Tells a customer privately about a change to their order.
+async fn notify_customer(bot: &Bot, user_id: i64, text: String) {
+    if let Err(err) = bot.send_message(ChatId(user_id), text).await {
+        tracing::warn!(
+            "Failed to notify user {} about their order: {}",
+            user_id,
+            err
+        );
+    }
+}
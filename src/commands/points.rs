@@ -0,0 +1,134 @@
+use crate::loyalty::PointsReason;
+use crate::schema::HandlerResult;
+use crate::utils::{delete_command, Config};
+use format as f;
+use sqlx::{SqliteConnection, SqliteExecutor, SqlitePool};
+use teloxide::prelude::*;
+
+/// Shows the user's loyalty points balance and its latest changes.
+pub async fn points(bot: Bot, msg: Message, pool: SqlitePool, config: Config) -> HandlerResult {
+    tracing::info!("processing /points command in chat {}", msg.chat.id);
+
+    delete_command(&bot, &msg).await;
+
+    if !config.loyalty.enabled {
+        bot.send_message(msg.chat.id, "The store has no loyalty program.")
+            .await?;
+        return Ok(());
+    }
+
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    let balance = fetch_points_balance(&pool, user_id).await?;
+
+    let entries = sqlx::query!(
+        r#"SELECT points, reason, order_id,
+            created_at AS "created_at!: chrono::NaiveDateTime"
+        FROM loyalty_points WHERE user_id = ? ORDER BY id DESC LIMIT 20"#,
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let history = if entries.is_empty() {
+        "\n\nFulfilled orders earn points you can redeem from your cart.".to_owned()
+    } else {
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                f!(
+                    "{} - {:+} {}",
+                    entry.created_at.format("%Y-%m-%d %H:%M"),
+                    entry.points,
+                    PointsReason::from_db(&entry.reason)
+                        .map(|reason| reason.describe(entry.order_id))
+                        .unwrap_or_else(|| entry.reason.clone())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        f!("\n\nHistory:\n{entries}")
+    };
+
+    bot.send_message(
+        msg.chat.id,
+        f!(
+            "{}{history}",
+            config.loyalty.describe_balance(balance, &config.currency)
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_points_balance(
+    executor: impl SqliteExecutor<'_>,
+    user_id: i64,
+) -> Result<i64, sqlx::Error> {
+    let balance = sqlx::query!(
+        r#"SELECT COALESCE(SUM(points), 0) AS "balance!: i64" FROM loyalty_points
+        WHERE user_id = ?"#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(balance.balance)
+}
+
+/// Adds an entry to the user's points ledger. Redeemed points are recorded as negative.
+pub async fn record_points(
+    executor: impl SqliteExecutor<'_>,
+    user_id: i64,
+    order_id: i64,
+    points: i64,
+    reason: PointsReason,
+) -> Result<(), sqlx::Error> {
+    let reason = reason.as_str();
+
+    sqlx::query!(
+        "INSERT INTO loyalty_points (user_id, order_id, points, reason) VALUES (?, ?, ?, ?)",
+        user_id,
+        order_id,
+        points,
+        reason
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Cancels out everything an order added to or took from customers' points, returning the
+/// points given back to each customer, which are negative for points taken back.
+pub async fn reverse_points(
+    conn: &mut SqliteConnection,
+    order_id: i64,
+) -> Result<Vec<(i64, i64)>, sqlx::Error> {
+    let totals = sqlx::query!(
+        r#"SELECT user_id, SUM(points) AS "points!: i64" FROM loyalty_points
+        WHERE order_id = ? GROUP BY user_id"#,
+        order_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut reversed = vec![];
+
+    for total in totals.into_iter().filter(|total| total.points != 0) {
+        record_points(
+            &mut *conn,
+            total.user_id,
+            order_id,
+            -total.points,
+            PointsReason::Reversed,
+        )
+        .await?;
+
+        reversed.push((total.user_id, -total.points));
+    }
+
+    Ok(reversed)
+}
@@ -0,0 +1,292 @@
+use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::delete_command;
+use crate::State;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{
+    prelude::*,
+    types::{
+        ButtonRequest, ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton,
+        KeyboardMarkup, KeyboardRemove,
+    },
+};
+
+/// Most addresses a customer can save.
+const MAX_ADDRESSES: i64 = 5;
+
+/// Shown when the profile is used outside a private chat, where other members would see it.
+const PRIVATE_ONLY: &str = "Please, manage your profile in a private chat with me.";
+
+/// Shows the user's phone number and saved addresses with buttons to change them.
+pub async fn profile(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+    tracing::info!("processing /profile command in chat {}", msg.chat.id);
+
+    delete_command(&bot, &msg).await;
+
+    if !msg.chat.is_private() {
+        bot.send_message(msg.chat.id, PRIVATE_ONLY).await?;
+        return Ok(());
+    }
+
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    send_profile(&bot, msg.chat.id, &pool, user_id).await
+}
+
+async fn send_profile(
+    bot: &Bot,
+    chat_id: ChatId,
+    pool: &SqlitePool,
+    user_id: i64,
+) -> HandlerResult {
+    let user = sqlx::query!("SELECT phone FROM users WHERE id = ?", user_id)
+        .fetch_one(pool)
+        .await?;
+
+    let addresses = sqlx::query!(
+        r#"SELECT id AS "id!", label, address FROM addresses WHERE user_id = ? ORDER BY id"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let address_list = if addresses.is_empty() {
+        "none".to_owned()
+    } else {
+        addresses
+            .iter()
+            .map(|address| f!("\n{}: {}", address.label, address.address))
+            .collect::<String>()
+    };
+
+    let mut keyboard = vec![vec![
+        InlineKeyboardButton::callback(
+            if user.phone.is_some() {
+                "Change Phone"
+            } else {
+                "Share Phone"
+            },
+            "profile phone",
+        ),
+        InlineKeyboardButton::callback("Add Address", "profile add_address"),
+    ]];
+
+    keyboard.extend(addresses.iter().map(|address| {
+        vec![InlineKeyboardButton::callback(
+            f!("✕ {}", address.label),
+            f!("remove_address {}", address.id),
+        )]
+    }));
+
+    bot.send_message(
+        chat_id,
+        f!(
+            "Your profile:\n\nPhone: {}\n\nAddresses: {address_list}",
+            user.phone.as_deref().unwrap_or("none")
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new(keyboard))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn profile_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    action: &str,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    let chat_id = match q.chat_id() {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+
+    // Telegram also only shares phone numbers in private chats.
+    if !chat_id.is_user() {
+        bot.answer_callback_query(q.id).text(PRIVATE_ONLY).await?;
+        return Ok(());
+    }
+
+    match action {
+        "phone" => {
+            dialogue.update(State::ReceiveProfilePhone).await?;
+
+            bot.send_message(
+                chat_id,
+                "Please, send me your phone number with the button below.",
+            )
+            .reply_markup(
+                KeyboardMarkup::new([[
+                    KeyboardButton::new("Share Phone Number").request(ButtonRequest::Contact)
+                ]])
+                .resize_keyboard(true)
+                .one_time_keyboard(true),
+            )
+            .await?;
+        }
+        "add_address" => {
+            let addresses = sqlx::query!(
+                r#"SELECT COUNT(*) AS "count!: i64" FROM addresses WHERE user_id = ?"#,
+                user_id
+            )
+            .fetch_one(&pool)
+            .await?;
+
+            if addresses.count >= MAX_ADDRESSES {
+                bot.answer_callback_query(q.id)
+                    .text(f!(
+                        "You can save at most {MAX_ADDRESSES} addresses. Remove one first."
+                    ))
+                    .await?;
+                return Ok(());
+            }
+
+            dialogue.update(State::ReceiveAddressLabel).await?;
+
+            bot.send_message(
+                chat_id,
+                "Please, send me a label for the address, such as Home or Work.",
+            )
+            .reply_markup(ForceReply::default())
+            .await?;
+        }
+        _ => {}
+    }
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+pub async fn receive_profile_phone(
+    bot: Bot,
+    msg: Message,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+) -> HandlerResult {
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    // A forwarded contact card would carry someone else's number.
+    let phone = match msg.contact() {
+        Some(contact) if contact.user_id.map(|id| id.0 as i64) == Some(user_id) => {
+            contact.phone_number.clone()
+        }
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me your own phone number with the button below, or /cancel.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    sqlx::query!("UPDATE users SET phone = ? WHERE id = ?", phone, user_id)
+        .execute(&pool)
+        .await?;
+
+    dialogue.exit().await?;
+
+    bot.send_message(msg.chat.id, "Phone number saved.")
+        .reply_markup(KeyboardRemove::new())
+        .await?;
+
+    send_profile(&bot, msg.chat.id, &pool, user_id).await
+}
+
+pub async fn receive_address_label(bot: Bot, msg: Message, dialogue: AppDialogue) -> HandlerResult {
+    match msg.text().map(str::trim) {
+        Some(label) if !label.is_empty() && label.chars().count() <= 32 => {
+            tracing::info!("address label: {}", label);
+
+            dialogue
+                .update(State::ReceiveAddress {
+                    label: label.to_owned(),
+                })
+                .await?;
+
+            bot.send_message(msg.chat.id, f!("Please, send me the {label} address."))
+                .reply_markup(ForceReply::default())
+                .await?;
+        }
+        _ => {
+            bot.send_message(
+                msg.chat.id,
+                "Please, send me a label of at most 32 characters.",
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn receive_address(
+    bot: Bot,
+    msg: Message,
+    dialogue: AppDialogue,
+    pool: SqlitePool,
+    label: String,
+) -> HandlerResult {
+    let address = match msg.text().map(str::trim) {
+        Some(address) if !address.is_empty() => address,
+        _ => {
+            bot.send_message(msg.chat.id, "Please, send me the address.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    sqlx::query!(
+        "INSERT INTO addresses (user_id, label, address) VALUES (?, ?, ?)",
+        user_id,
+        label,
+        address
+    )
+    .execute(&pool)
+    .await?;
+
+    dialogue.exit().await?;
+
+    send_profile(&bot, msg.chat.id, &pool, user_id).await
+}
+
+pub async fn remove_address_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    address_id: i64,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    if !q.chat_id().is_some_and(|chat_id| chat_id.is_user()) {
+        bot.answer_callback_query(q.id).text(PRIVATE_ONLY).await?;
+        return Ok(());
+    }
+
+    sqlx::query!(
+        "DELETE FROM addresses WHERE id = ? AND user_id = ?",
+        address_id,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if let (Some(chat_id), Some(message)) = (q.chat_id(), &q.message) {
+        bot.delete_message(chat_id, message.id).await?;
+        send_profile(&bot, chat_id, &pool, user_id).await?;
+    }
+
+    bot.answer_callback_query(q.id)
+        .text("Address removed.")
+        .await?;
+
+    Ok(())
+}
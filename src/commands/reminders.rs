@@ -0,0 +1,68 @@
+use crate::schema::HandlerResult;
+use crate::utils::delete_command;
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::prelude::*;
+
+/// Turns abandoned cart reminders on or off for the user.
+pub async fn reminders(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+    tracing::info!("processing /reminders command in chat {}", msg.chat.id);
+
+    delete_command(&bot, &msg).await;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    let user = sqlx::query!(
+        "UPDATE users SET cart_reminders = NOT cart_reminders WHERE id = ? RETURNING cart_reminders",
+        id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    match user {
+        Some(user) => {
+            bot.send_message(msg.chat.id, reminders_text(user.cart_reminders))
+                .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Please, use /start first.")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn cart_reminders_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    enabled: bool,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    sqlx::query!(
+        "UPDATE users SET cart_reminders = ? WHERE id = ?",
+        enabled,
+        user_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if let Some(chat_id) = q.chat_id() {
+        bot.send_message(chat_id, reminders_text(enabled)).await?;
+    }
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+fn reminders_text(enabled: bool) -> String {
+    f!(
+        "Cart reminders are now {}. Use /reminders to turn them {}.",
+        if enabled { "on" } else { "off" },
+        if enabled { "off" } else { "on" }
+    )
+}
@@ -1,8 +1,9 @@
 use crate::schema::{AppDialogue, HandlerResult};
 use crate::{utils::assert_admin_id, State};
+use format as f;
 use sqlx::SqlitePool;
 use teloxide::prelude::*;
-use teloxide::types::ForceReply;
+use teloxide::types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup};
 
 pub async fn remove_product(bot: Bot, msg: Message, dialogue: AppDialogue) -> HandlerResult {
     tracing::info!("processing /remove command in chat {}", msg.chat.id);
@@ -37,18 +38,36 @@ pub async fn receive_product_id(
                 }
             };
 
-            if let Err(err) = sqlx::query!("DELETE FROM products WHERE id = ?", product_id)
-                .execute(&pool)
-                .await
+            // Products are archived rather than deleted so order history keeps them.
+            match sqlx::query!(
+                "UPDATE products SET archived = TRUE WHERE id = ? AND NOT archived",
+                product_id
+            )
+            .execute(&pool)
+            .await
             {
-                tracing::error!("Error: {}", err);
-                bot.send_message(msg.chat.id, "Invalid product id.").await?;
-                return Ok(());
+                Ok(result) if result.rows_affected() > 0 => {}
+                Ok(_) => {
+                    bot.send_message(msg.chat.id, "Invalid product id.").await?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    tracing::error!("Error: {}", err);
+                    bot.send_message(msg.chat.id, "Invalid product id.").await?;
+                    return Ok(());
+                }
             };
 
-            bot.send_message(msg.chat.id, "Product removed successfully.")
+            sqlx::query!("DELETE FROM cart_items WHERE product_id = ?", product_id)
+                .execute(&pool)
                 .await?;
 
+            bot.send_message(
+                msg.chat.id,
+                "Product archived successfully. Use /restore to bring it back.",
+            )
+            .await?;
+
             dialogue.exit().await?;
         }
         None => {
@@ -59,3 +78,67 @@ pub async fn receive_product_id(
 
     Ok(())
 }
+
+pub async fn restore_product(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+    tracing::info!("processing /restore command in chat {}", msg.chat.id);
+
+    bot.delete_message(msg.chat.id, msg.id).await?;
+
+    let id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+    assert_admin_id(id)?;
+
+    let products = sqlx::query!("SELECT id, name FROM products WHERE archived ORDER BY name")
+        .fetch_all(&pool)
+        .await?;
+
+    if products.is_empty() {
+        bot.send_message(msg.chat.id, "There are no archived products.")
+            .await?;
+        return Ok(());
+    }
+
+    let products = products
+        .into_iter()
+        .map(|product| {
+            vec![InlineKeyboardButton::callback(
+                f!("#{} {}", product.id, product.name),
+                f!("restore_product {}", product.id),
+            )]
+        })
+        .collect::<Vec<_>>();
+
+    bot.send_message(msg.chat.id, "Select a product to restore:")
+        .reply_markup(InlineKeyboardMarkup::new(products))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn restore_product_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    product_id: i64,
+) -> HandlerResult {
+    let admin_id = q.from.id.to_string().parse::<i64>()?;
+    assert_admin_id(admin_id)?;
+
+    let product = sqlx::query!(
+        "UPDATE products SET archived = FALSE WHERE id = ? AND archived RETURNING name",
+        product_id
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    let text = match product {
+        Some(product) => {
+            tracing::info!("admin {} restored product {}", admin_id, product_id);
+            f!("Product {} restored.", product.name)
+        }
+        None => "No archived product has that id.".to_owned(),
+    };
+
+    bot.answer_callback_query(q.id).text(text).await?;
+
+    Ok(())
+}
@@ -5,11 +5,12 @@ use teloxide::{
 use url::Url;
 
 use crate::schema::{AppDialogue, HandlerResult};
+use crate::utils::delete_command;
 
 pub async fn shop(bot: Bot, dialogue: AppDialogue, msg: Message) -> HandlerResult {
     tracing::info!("processing /cancel command in chat {}", msg.chat.id);
 
-    bot.delete_message(msg.chat.id, msg.id).await?;
+    delete_command(&bot, &msg).await;
 
     bot.send_message(msg.chat.id, "Cancelled the dialogue.")
         .await?;
@@ -1,8 +1,73 @@
+use crate::commands::inventory::send_product;
 use crate::schema::HandlerResult;
+use crate::utils::Config;
 use sqlx::SqlitePool;
-use teloxide::prelude::*;
+use teloxide::{prelude::*, types::User};
 
-pub async fn start(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
+/// Creates the user and their cart on their first interaction of any kind, and keeps their
+/// names and last seen time current. Later updates only write when the names changed or the last
+/// seen time is more than a few minutes old, so typing an inline query doesn't write on every key.
+pub async fn register_user(update: Update, pool: SqlitePool) {
+    let user = match update.user() {
+        Some(user) => user,
+        None => return,
+    };
+
+    if let Err(err) = upsert_user(&pool, user).await {
+        tracing::error!("Failed to register user {}: {}", user.id, err);
+    }
+}
+
+async fn upsert_user(pool: &SqlitePool, user: &User) -> Result<(), sqlx::Error> {
+    let id = user.id.0 as i64;
+
+    let known = sqlx::query!(
+        r#"SELECT username, first_name, last_name,
+            COALESCE(last_seen > datetime('now', '-5 minutes'), FALSE) AS "recent!: bool"
+        FROM users WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(known) = known {
+        if known.recent
+            && known.username == user.username
+            && known.first_name == user.first_name
+            && known.last_name == user.last_name
+        {
+            return Ok(());
+        }
+    }
+
+    sqlx::query!(
+        "INSERT INTO users (id, username, first_name, last_name, last_seen)
+        VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT (id) DO UPDATE SET username = excluded.username,
+            first_name = excluded.first_name, last_name = excluded.last_name,
+            last_seen = excluded.last_seen",
+        id,
+        user.username,
+        user.first_name,
+        user.last_name
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!("INSERT OR IGNORE INTO carts (user_id) VALUES (?)", id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn start(
+    bot: Bot,
+    msg: Message,
+    pool: SqlitePool,
+    config: Config,
+    payload: String,
+) -> HandlerResult {
     tracing::info!("processing /start command in chat {}", msg.chat.id);
 
     let from = match msg.from() {
@@ -16,22 +81,16 @@ pub async fn start(bot: Bot, msg: Message, pool: SqlitePool) -> HandlerResult {
 
     let id = from.id.to_string().parse::<i64>()?;
 
-    sqlx::query!(
-        "INSERT OR IGNORE INTO users (id, username, first_name, last_name) VALUES (?, ?, ?, ?)",
-        id,
-        from.username,
-        from.first_name,
-        from.last_name
-    )
-    .execute(&pool)
-    .await?;
-
-    sqlx::query!("INSERT OR IGNORE INTO carts (user_id) VALUES (?)", id)
-        .execute(&pool)
-        .await?;
-
     bot.send_message(msg.chat.id, "Welcome to the store!")
         .await?;
 
+    // Deep links shared from inline mode open straight into the product.
+    if let Some(product_id) = payload
+        .strip_prefix("product_")
+        .and_then(|id| id.parse::<i64>().ok())
+    {
+        send_product(&bot, msg.chat.id, &pool, &config, product_id, id).await?;
+    }
+
     Ok(())
 }
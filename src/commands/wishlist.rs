@@ -0,0 +1,261 @@
+use crate::commands::inventory::{fetch_product_images, product_keyboard};
+use crate::money::Money;
+use crate::schema::HandlerResult;
+use crate::utils::{delete_command, Config};
+use format as f;
+use sqlx::SqlitePool;
+use teloxide::dispatching::dialogue::GetChatId;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+/// Shows the user's saved products.
+pub async fn wishlist(bot: Bot, msg: Message, pool: SqlitePool, config: Config) -> HandlerResult {
+    tracing::info!("processing /wishlist command in chat {}", msg.chat.id);
+
+    delete_command(&bot, &msg).await;
+
+    let user_id = msg.from().unwrap().id.to_string().parse::<i64>()?;
+
+    let (text, keyboard) = render_wishlist(&pool, &config, user_id).await?;
+
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await?;
+
+    Ok(())
+}
+
+async fn render_wishlist(
+    pool: &SqlitePool,
+    config: &Config,
+    user_id: i64,
+) -> Result<(String, InlineKeyboardMarkup), sqlx::Error> {
+    let products = sqlx::query!(
+        r#"SELECT products.id AS "id!", products.name, products.price,
+            (products.visible AND NOT products.archived AND products.in_stock) AS "available!: bool"
+        FROM wishlist_items
+        INNER JOIN products ON wishlist_items.product_id = products.id
+        WHERE wishlist_items.user_id = ?
+        ORDER BY wishlist_items.created_at, wishlist_items.id"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if products.is_empty() {
+        return Ok((
+            "Your wishlist is empty. Tap ♡ Save on a product to be told when it's back in stock or cheaper.".to_owned(),
+            InlineKeyboardMarkup::default(),
+        ));
+    }
+
+    let keyboard = products
+        .into_iter()
+        .map(|product| {
+            let label = f!(
+                "{} - {}{}",
+                product.name,
+                Money::from_cents(product.price).format(&config.currency),
+                if product.available { "" } else { " (sold out)" }
+            );
+
+            vec![
+                InlineKeyboardButton::callback(label, f!("view_product {}", product.id)),
+                InlineKeyboardButton::callback("✕", f!("wishlist_remove {}", product.id)),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    Ok((
+        "Your wishlist. You'll be told when a product is back in stock or drops in price."
+            .to_owned(),
+        InlineKeyboardMarkup::new(keyboard),
+    ))
+}
+
+pub async fn is_saved(
+    pool: &SqlitePool,
+    user_id: i64,
+    product_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let saved = sqlx::query!(
+        "SELECT id FROM wishlist_items WHERE user_id = ? AND product_id = ?",
+        user_id,
+        product_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(saved.is_some())
+}
+
+/// Saves the product to the user's wishlist, or removes it if it is already saved, and updates
+/// the button on the product card showing gallery image `index`.
+pub async fn save_product_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    product_id: i64,
+    index: usize,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    let removed = sqlx::query!(
+        "DELETE FROM wishlist_items WHERE user_id = ? AND product_id = ?",
+        user_id,
+        product_id
+    )
+    .execute(&pool)
+    .await?
+    .rows_affected()
+        > 0;
+
+    // The product's current state is recorded, so only later changes are announced.
+    if !removed {
+        sqlx::query!(
+            "INSERT INTO wishlist_items (user_id, product_id, available, price)
+            SELECT ?, id, visible AND NOT archived AND in_stock, price FROM products WHERE id = ?",
+            user_id,
+            product_id
+        )
+        .execute(&pool)
+        .await?;
+    }
+
+    let product = sqlx::query!("SELECT in_stock FROM products WHERE id = ?", product_id)
+        .fetch_optional(&pool)
+        .await?;
+
+    if let (Some(chat_id), Some(message), Some(product)) = (q.chat_id(), &q.message, product) {
+        let images = fetch_product_images(&pool, product_id).await?;
+
+        bot.edit_message_reply_markup(chat_id, message.id)
+            .reply_markup(product_keyboard(
+                product_id,
+                index,
+                images.len(),
+                product.in_stock,
+                !removed,
+            ))
+            .await?;
+    }
+
+    bot.answer_callback_query(q.id)
+        .text(if removed {
+            "Removed from your wishlist."
+        } else {
+            "Saved to your wishlist."
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub async fn wishlist_remove_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: SqlitePool,
+    config: Config,
+    product_id: i64,
+) -> HandlerResult {
+    let user_id = q.from.id.to_string().parse::<i64>()?;
+
+    sqlx::query!(
+        "DELETE FROM wishlist_items WHERE user_id = ? AND product_id = ?",
+        user_id,
+        product_id
+    )
+    .execute(&pool)
+    .await?;
+
+    if let (Some(chat_id), Some(message)) = (q.chat_id(), &q.message) {
+        let (text, keyboard) = render_wishlist(&pool, &config, user_id).await?;
+
+        bot.edit_message_text(chat_id, message.id, text)
+            .reply_markup(keyboard)
+            .await?;
+    }
+
+    bot.answer_callback_query(q.id).await?;
+
+    Ok(())
+}
+
+/// Tells customers about saved products that came back in stock or dropped in price since they
+/// were last told, then records what they were told so each change is announced once.
+pub async fn notify_wishlists(
+    bot: &Bot,
+    pool: &SqlitePool,
+    config: &Config,
+) -> Result<(), sqlx::Error> {
+    let items = sqlx::query!(
+        r#"SELECT wishlist_items.id AS "id!", wishlist_items.user_id,
+            wishlist_items.available AS seen_available, wishlist_items.price AS seen_price,
+            products.id AS "product_id!", products.name, products.price,
+            (products.visible AND NOT products.archived AND products.in_stock) AS "available!: bool"
+        FROM wishlist_items
+        INNER JOIN products ON wishlist_items.product_id = products.id
+        INNER JOIN users ON wishlist_items.user_id = users.id
+        WHERE users.status != 'banned'
+            AND (wishlist_items.available != (products.visible AND NOT products.archived AND products.in_stock)
+                OR wishlist_items.price != products.price)"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for item in items {
+        let price = Money::from_cents(item.price).format(&config.currency);
+        let seen_price = Money::from_cents(item.seen_price).format(&config.currency);
+
+        // Products going out of stock or getting more expensive are recorded silently.
+        let text = if item.available && !item.seen_available {
+            Some(if item.price < item.seen_price {
+                f!(
+                    "{} is back in stock for {price} (was {seen_price})!",
+                    item.name
+                )
+            } else {
+                f!("{} is back in stock for {price}!", item.name)
+            })
+        } else if item.available && item.price < item.seen_price {
+            Some(f!(
+                "{} dropped in price from {seen_price} to {price}!",
+                item.name
+            ))
+        } else {
+            None
+        };
+
+        if let Some(text) = text {
+            let sent = bot
+                .send_message(ChatId(item.user_id), text)
+                .reply_markup(InlineKeyboardMarkup::new([vec![
+                    InlineKeyboardButton::callback("View", f!("view_product {}", item.product_id)),
+                ]]))
+                .await;
+
+            // Like cart reminders, a failed notification is not retried.
+            if let Err(err) = sent {
+                tracing::warn!(
+                    "Failed to notify user {} about product {}: {}",
+                    item.user_id,
+                    item.product_id,
+                    err
+                );
+            }
+        }
+
+        sqlx::query!(
+            "UPDATE wishlist_items SET available = ?, price = ? WHERE id = ?",
+            item.available,
+            item.price,
+            item.id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
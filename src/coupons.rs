@@ -0,0 +1,254 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use format as f;
+
+use crate::money::{Currency, Money};
+use crate::pricing::Discount;
+
+/// A discount code created by an admin with `/coupon`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coupon {
+    pub code: String,
+    pub discount: Discount,
+    pub min_order: Money,
+    /// Last day on which the coupon can be used.
+    pub expires_on: Option<NaiveDate>,
+    pub max_uses: Option<i64>,
+    pub max_uses_per_user: Option<i64>,
+}
+
+/// Why a coupon cannot be applied to a cart.
+#[derive(Debug, PartialEq)]
+pub enum CouponError {
+    Expired,
+    MinimumOrder(Money),
+    UsedUp,
+    AlreadyUsed,
+}
+
+impl Coupon {
+    /// Checks the coupon against a cart subtotal and how often it has been redeemed.
+    pub fn check(
+        &self,
+        subtotal: Money,
+        today: NaiveDate,
+        uses: i64,
+        user_uses: i64,
+    ) -> Result<(), CouponError> {
+        if self.expires_on.is_some_and(|expires_on| today > expires_on) {
+            return Err(CouponError::Expired);
+        }
+
+        if subtotal < self.min_order {
+            return Err(CouponError::MinimumOrder(self.min_order));
+        }
+
+        if self.max_uses.is_some_and(|max_uses| uses >= max_uses) {
+            return Err(CouponError::UsedUp);
+        }
+
+        if self
+            .max_uses_per_user
+            .is_some_and(|max_uses| user_uses >= max_uses)
+        {
+            return Err(CouponError::AlreadyUsed);
+        }
+
+        Ok(())
+    }
+
+    pub fn describe(&self, currency: &Currency) -> String {
+        let mut description = match self.discount {
+            Discount::Percent(percent) => f!("{} - {percent}% off", self.code),
+            Discount::Fixed(amount) => f!("{} - {} off", self.code, amount.format(currency)),
+        };
+
+        if self.min_order > Money::ZERO {
+            description.push_str(&f!(", min {}", self.min_order.format(currency)));
+        }
+        if let Some(expires_on) = self.expires_on {
+            description.push_str(&f!(", until {expires_on}"));
+        }
+        if let Some(max_uses) = self.max_uses {
+            description.push_str(&f!(", {max_uses} uses"));
+        }
+        if let Some(max_uses) = self.max_uses_per_user {
+            description.push_str(&f!(", {max_uses} per customer"));
+        }
+
+        description
+    }
+}
+
+impl CouponError {
+    pub fn message(&self, currency: &Currency) -> String {
+        match self {
+            CouponError::Expired => "This coupon has expired.".to_owned(),
+            CouponError::MinimumOrder(min_order) => f!(
+                "This coupon requires an order of at least {}.",
+                min_order.format(currency)
+            ),
+            CouponError::UsedUp => "This coupon is no longer available.".to_owned(),
+            CouponError::AlreadyUsed => "You have already used this coupon.".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseCouponError(String);
+
+impl fmt::Display for ParseCouponError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseCouponError {}
+
+/// Parses the arguments of `/coupon`, such as
+/// `SUMMER10 10% min=20 expires=2026-08-31 uses=100 per_user=1`.
+pub fn parse_coupon(input: &str, currency: &Currency) -> Result<Coupon, ParseCouponError> {
+    let error = |message: String| ParseCouponError(message);
+
+    let mut args = input.split_whitespace();
+
+    let code = match args.next() {
+        Some(code)
+            if code
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            code.to_uppercase()
+        }
+        Some(code) => return Err(error(f!("invalid code {code:?}"))),
+        None => return Err(error("missing code".to_owned())),
+    };
+
+    let discount = match args.next() {
+        Some(value) => match value.strip_suffix('%') {
+            Some(percent) => match percent.parse::<i64>() {
+                Ok(percent) if (1..=100).contains(&percent) => Discount::Percent(percent),
+                _ => return Err(error(f!("invalid percentage {value:?}"))),
+            },
+            None => match Money::parse(value, currency) {
+                Ok(amount) if amount > Money::ZERO => Discount::Fixed(amount),
+                _ => return Err(error(f!("invalid discount {value:?}"))),
+            },
+        },
+        None => return Err(error("missing discount".to_owned())),
+    };
+
+    let mut coupon = Coupon {
+        code,
+        discount,
+        min_order: Money::ZERO,
+        expires_on: None,
+        max_uses: None,
+        max_uses_per_user: None,
+    };
+
+    let count = |value: &str| match value.parse::<i64>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(error(f!("invalid count {value:?}"))),
+    };
+
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("min", value)) => {
+                coupon.min_order = Money::parse(value, currency)
+                    .map_err(|_| error(f!("invalid minimum order {value:?}")))?;
+            }
+            Some(("expires", value)) => {
+                coupon.expires_on = Some(
+                    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                        .map_err(|_| error(f!("invalid date {value:?}, expected YYYY-MM-DD")))?,
+                );
+            }
+            Some(("uses", value)) => coupon.max_uses = Some(count(value)?),
+            Some(("per_user", value)) => coupon.max_uses_per_user = Some(count(value)?),
+            _ => return Err(error(f!("unknown option {arg:?}"))),
+        }
+    }
+
+    Ok(coupon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_coupon() {
+        let currency = Currency::default();
+
+        assert_eq!(
+            parse_coupon(
+                "summer10 10% min=20 expires=2026-08-31 uses=100 per_user=1",
+                &currency
+            ),
+            Ok(Coupon {
+                code: "SUMMER10".to_owned(),
+                discount: Discount::Percent(10),
+                min_order: Money::from_cents(2000),
+                expires_on: Some(date("2026-08-31")),
+                max_uses: Some(100),
+                max_uses_per_user: Some(1),
+            })
+        );
+
+        assert_eq!(
+            parse_coupon("FIVE $5", &currency).map(|coupon| coupon.discount),
+            Ok(Discount::Fixed(Money::from_cents(500)))
+        );
+    }
+
+    #[test]
+    fn test_parse_coupon_errors() {
+        let currency = Currency::default();
+
+        assert!(parse_coupon("", &currency).is_err());
+        assert!(parse_coupon("CODE", &currency).is_err());
+        assert!(parse_coupon("CO DE", &currency).is_err());
+        assert!(parse_coupon("CODE 0%", &currency).is_err());
+        assert!(parse_coupon("CODE 101%", &currency).is_err());
+        assert!(parse_coupon("CODE 0", &currency).is_err());
+        assert!(parse_coupon("CODE 10% expires=tomorrow", &currency).is_err());
+        assert!(parse_coupon("CODE 10% uses=0", &currency).is_err());
+        assert!(parse_coupon("CODE 10% limit=1", &currency).is_err());
+        assert!(parse_coupon("CÓDIGO 10%", &currency).is_err());
+    }
+
+    #[test]
+    fn test_check_coupon() {
+        let coupon = parse_coupon(
+            "CODE 10% min=20 expires=2026-08-31 uses=2 per_user=1",
+            &Currency::default(),
+        )
+        .unwrap();
+        let today = date("2026-08-31");
+        let subtotal = Money::from_cents(2000);
+
+        assert_eq!(coupon.check(subtotal, today, 0, 0), Ok(()));
+        assert_eq!(
+            coupon.check(subtotal, date("2026-09-01"), 0, 0),
+            Err(CouponError::Expired)
+        );
+        assert_eq!(
+            coupon.check(Money::from_cents(1999), today, 0, 0),
+            Err(CouponError::MinimumOrder(subtotal))
+        );
+        assert_eq!(
+            coupon.check(subtotal, today, 2, 0),
+            Err(CouponError::UsedUp)
+        );
+        assert_eq!(
+            coupon.check(subtotal, today, 1, 1),
+            Err(CouponError::AlreadyUsed)
+        );
+    }
+}
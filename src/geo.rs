@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+/// Mean Earth radius used for distances.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A point given in degrees, such as the store's `store_location` in `Config.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Great-circle distance to another point, in kilometres.
+    pub fn distance_km(self, other: Location) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// An area the store delivers to, configured as `[[delivery_zones]]` in `Config.toml`, with the
+/// delivery fee charged there.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryZone {
+    pub name: String,
+    #[serde(flatten)]
+    pub area: ZoneArea,
+    pub fee: Money,
+}
+
+/// Either a radius around the store or a polygon of `[latitude, longitude]` corners.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ZoneArea {
+    Radius { radius_km: f64 },
+    Polygon { polygon: Vec<[f64; 2]> },
+}
+
+impl DeliveryZone {
+    pub fn contains(&self, store: Location, point: Location) -> bool {
+        match &self.area {
+            ZoneArea::Radius { radius_km } => store.distance_km(point) <= *radius_km,
+            ZoneArea::Polygon { polygon } => in_polygon(polygon, point),
+        }
+    }
+}
+
+/// The first zone containing `point`, so smaller zones should be listed before the larger ones
+/// around them.
+pub fn find_zone(
+    zones: &[DeliveryZone],
+    store: Location,
+    point: Location,
+) -> Option<&DeliveryZone> {
+    zones.iter().find(|zone| zone.contains(store, point))
+}
+
+/// Ray casting with longitude as x and latitude as y, which is accurate enough for zones the
+/// size of a city.
+fn in_polygon(polygon: &[[f64; 2]], point: Location) -> bool {
+    let (x, y) = (point.longitude, point.latitude);
+    let mut inside = false;
+
+    for (index, [lat1, lon1]) in polygon.iter().enumerate() {
+        let [lat2, lon2] = polygon[(index + 1) % polygon.len()];
+
+        if (*lat1 > y) != (lat2 > y) && x < (lon2 - lon1) * (y - lat1) / (lat2 - lat1) + lon1 {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(latitude: f64, longitude: f64) -> Location {
+        Location {
+            latitude,
+            longitude,
+        }
+    }
+
+    fn zones() -> Vec<DeliveryZone> {
+        vec![
+            DeliveryZone {
+                name: "Nearby".to_owned(),
+                area: ZoneArea::Radius { radius_km: 2.0 },
+                fee: Money::from_cents(300),
+            },
+            DeliveryZone {
+                name: "Square".to_owned(),
+                area: ZoneArea::Polygon {
+                    polygon: vec![[1.0, 1.0], [1.0, 2.0], [2.0, 2.0], [2.0, 1.0]],
+                },
+                fee: Money::from_cents(600),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_distance() {
+        // One degree of latitude is about 111 km.
+        let distance = location(0.0, 0.0).distance_km(location(1.0, 0.0));
+        assert!((distance - 111.19).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_find_zone() {
+        let store = location(0.0, 0.0);
+        let zones = zones();
+        let name = |point| find_zone(&zones, store, point).map(|zone| zone.name.as_str());
+
+        assert_eq!(name(location(0.01, 0.01)), Some("Nearby"));
+        assert_eq!(name(location(1.5, 1.5)), Some("Square"));
+        assert_eq!(name(location(1.5, 2.5)), None);
+        assert_eq!(name(location(0.5, 0.5)), None);
+    }
+}
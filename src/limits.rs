@@ -0,0 +1,151 @@
+use std::fmt;
+
+/// Why a cart quantity is not accepted.
+#[derive(Debug, PartialEq)]
+pub enum QuantityError {
+    Negative,
+    ProductLimit { name: String, max: i64 },
+    OrderLimit { max: i64 },
+}
+
+impl fmt::Display for QuantityError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuantityError::Negative => {
+                write!(formatter, "The quantity can't be negative.")
+            }
+            QuantityError::ProductLimit { name, max } => {
+                write!(formatter, "You can order at most {max} of {name}.")
+            }
+            QuantityError::OrderLimit { max } => {
+                write!(formatter, "An order can contain at most {max} items.")
+            }
+        }
+    }
+}
+
+/// A cart item's product, quantity and the product's own limit.
+#[derive(Debug, Clone)]
+pub struct ItemQuantity {
+    pub name: String,
+    pub quantity: i64,
+    pub max_quantity: Option<i64>,
+}
+
+/// Checks changing `item` to `quantity` units, given the units of the cart's other items.
+/// Lowering a quantity is always accepted, so carts over a limit that was tightened later can
+/// still be fixed.
+pub fn check_quantity(
+    item: &ItemQuantity,
+    quantity: i64,
+    other_units: i64,
+    max_order_quantity: Option<i64>,
+) -> Result<(), QuantityError> {
+    if quantity < 0 {
+        return Err(QuantityError::Negative);
+    }
+
+    if quantity <= item.quantity {
+        return Ok(());
+    }
+
+    if let Some(max) = item.max_quantity.filter(|max| quantity > *max) {
+        return Err(QuantityError::ProductLimit {
+            name: item.name.clone(),
+            max,
+        });
+    }
+
+    if let Some(max) = max_order_quantity.filter(|max| other_units + quantity > *max) {
+        return Err(QuantityError::OrderLimit { max });
+    }
+
+    Ok(())
+}
+
+/// Checks a whole cart at checkout.
+pub fn check_cart(
+    items: &[ItemQuantity],
+    max_order_quantity: Option<i64>,
+) -> Result<(), QuantityError> {
+    for item in items {
+        if item.quantity < 0 {
+            return Err(QuantityError::Negative);
+        }
+
+        if let Some(max) = item.max_quantity.filter(|max| item.quantity > *max) {
+            return Err(QuantityError::ProductLimit {
+                name: item.name.clone(),
+                max,
+            });
+        }
+    }
+
+    let units = items.iter().map(|item| item.quantity).sum::<i64>();
+    if let Some(max) = max_order_quantity.filter(|max| units > *max) {
+        return Err(QuantityError::OrderLimit { max });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(quantity: i64, max_quantity: Option<i64>) -> ItemQuantity {
+        ItemQuantity {
+            name: "Runtz".to_owned(),
+            quantity,
+            max_quantity,
+        }
+    }
+
+    #[test]
+    fn test_check_quantity() {
+        let runtz = item(1, Some(3));
+
+        assert_eq!(check_quantity(&runtz, 3, 0, None), Ok(()));
+        assert_eq!(check_quantity(&runtz, 0, 0, None), Ok(()));
+        assert_eq!(
+            check_quantity(&runtz, -1, 0, None),
+            Err(QuantityError::Negative)
+        );
+        assert_eq!(
+            check_quantity(&runtz, 4, 0, None),
+            Err(QuantityError::ProductLimit {
+                name: "Runtz".to_owned(),
+                max: 3
+            })
+        );
+        assert_eq!(
+            check_quantity(&runtz, 3, 8, Some(10)),
+            Err(QuantityError::OrderLimit { max: 10 })
+        );
+        assert_eq!(check_quantity(&runtz, 2, 8, Some(10)), Ok(()));
+    }
+
+    #[test]
+    fn test_lowering_over_limit() {
+        assert_eq!(check_quantity(&item(5, Some(3)), 4, 20, Some(10)), Ok(()));
+    }
+
+    #[test]
+    fn test_check_cart() {
+        assert_eq!(
+            check_cart(&[item(3, Some(3)), item(5, None)], Some(8)),
+            Ok(())
+        );
+        assert_eq!(
+            check_cart(&[item(4, Some(3))], None),
+            Err(QuantityError::ProductLimit {
+                name: "Runtz".to_owned(),
+                max: 3
+            })
+        );
+        assert_eq!(
+            check_cart(&[item(3, None), item(6, None)], Some(8)),
+            Err(QuantityError::OrderLimit { max: 8 })
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use format as f;
+use serde::{Deserialize, Serialize};
+
+use crate::money::{Currency, Money};
+
+/// The loyalty points program, configured under `[loyalty]` in `Config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoyaltyConfig {
+    pub enabled: bool,
+    /// Points earned per whole unit of currency spent on fulfilled orders.
+    pub points_per_unit: i64,
+    /// What one point takes off an order when redeemed.
+    pub point_value: Money,
+}
+
+impl Default for LoyaltyConfig {
+    fn default() -> Self {
+        LoyaltyConfig {
+            enabled: false,
+            points_per_unit: 1,
+            point_value: Money::from_cents(1),
+        }
+    }
+}
+
+impl LoyaltyConfig {
+    /// Points earned for spending `amount`, rounded down.
+    pub fn points_earned(&self, amount: Money) -> i64 {
+        if !self.enabled {
+            return 0;
+        }
+
+        amount.cents().max(0).saturating_mul(self.points_per_unit) / 100
+    }
+
+    /// How many of a `balance` of points can be redeemed on an order of `total`, and what they
+    /// take off it. Points are never worth more than the order.
+    pub fn redeem(&self, balance: i64, total: Money) -> (i64, Money) {
+        let value = self.point_value.cents();
+
+        if !self.enabled || balance <= 0 || value <= 0 {
+            return (0, Money::ZERO);
+        }
+
+        let points = balance.min(total.cents().max(0) / value);
+
+        (points, Money::from_cents(points * value))
+    }
+
+    /// Describes a points balance. It goes negative when an order whose earned points were
+    /// already spent is cancelled, which is owed rather than shown with a negative value.
+    pub fn describe_balance(&self, balance: i64, currency: &Currency) -> String {
+        if balance < 0 {
+            return f!(
+                "You owe {} loyalty points for a cancelled order. They come off the next points you earn.",
+                -balance
+            );
+        }
+
+        let value = Money::from_cents(balance.saturating_mul(self.point_value.cents()));
+
+        f!(
+            "You have {balance} loyalty points, worth {}.",
+            value.format(currency)
+        )
+    }
+}
+
+/// Why points were added to or taken from a customer's ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointsReason {
+    Earned,
+    Redeemed,
+    /// An order's points given back after it was cancelled or refunded.
+    Reversed,
+}
+
+impl PointsReason {
+    /// How the reason is stored in `loyalty_points.reason`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PointsReason::Earned => "earned",
+            PointsReason::Redeemed => "redeemed",
+            PointsReason::Reversed => "reversed",
+        }
+    }
+
+    pub fn from_db(reason: &str) -> Option<PointsReason> {
+        match reason {
+            "earned" => Some(PointsReason::Earned),
+            "redeemed" => Some(PointsReason::Redeemed),
+            "reversed" => Some(PointsReason::Reversed),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self, order_id: i64) -> String {
+        match self {
+            PointsReason::Earned => f!("earned on order #{order_id}"),
+            PointsReason::Redeemed => f!("redeemed on order #{order_id}"),
+            PointsReason::Reversed => f!("reversed for cancelled order #{order_id}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LoyaltyConfig {
+        LoyaltyConfig {
+            enabled: true,
+            points_per_unit: 2,
+            point_value: Money::from_cents(5),
+        }
+    }
+
+    #[test]
+    fn test_points_earned() {
+        assert_eq!(config().points_earned(Money::from_cents(1999)), 39);
+        assert_eq!(config().points_earned(Money::from_cents(-100)), 0);
+        assert_eq!(
+            LoyaltyConfig::default().points_earned(Money::from_cents(1999)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_redeem() {
+        // The whole balance fits in the order.
+        assert_eq!(
+            config().redeem(100, Money::from_cents(2000)),
+            (100, Money::from_cents(500))
+        );
+        // Only as many points as the order is worth, without going below zero.
+        assert_eq!(
+            config().redeem(100, Money::from_cents(212)),
+            (42, Money::from_cents(210))
+        );
+        assert_eq!(
+            config().redeem(-10, Money::from_cents(2000)),
+            (0, Money::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_describe_balance() {
+        let dollar = Currency::default();
+
+        assert_eq!(
+            config().describe_balance(120, &dollar),
+            "You have 120 loyalty points, worth $6.00."
+        );
+        assert_eq!(
+            config().describe_balance(0, &dollar),
+            "You have 0 loyalty points, worth $0.00."
+        );
+        // Points taken back after they were spent are owed.
+        assert_eq!(
+            config().describe_balance(-15, &dollar),
+            "You owe 15 loyalty points for a cancelled order. They come off the next points you earn."
+        );
+    }
+
+    #[test]
+    fn test_points_reason() {
+        for reason in [
+            PointsReason::Earned,
+            PointsReason::Redeemed,
+            PointsReason::Reversed,
+        ] {
+            assert_eq!(PointsReason::from_db(reason.as_str()), Some(reason));
+        }
+
+        assert_eq!(
+            PointsReason::Reversed.describe(7),
+            "reversed for cancelled order #7"
+        );
+    }
+}
@@ -1,4 +1,15 @@
+mod catalog;
+mod checkout;
 mod commands;
+mod coupons;
+mod geo;
+mod limits;
+mod loyalty;
+mod moderation;
+mod money;
+mod pricing;
+mod promotions;
+mod scheduler;
 mod schema;
 mod utils;
 
@@ -7,6 +18,7 @@ use sqlx::SqlitePool;
 use std::env;
 use teloxide::utils::command::BotCommands;
 use teloxide::{dispatching::dialogue::InMemStorage, prelude::*};
+use utils::parse_config;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
@@ -23,10 +35,25 @@ async fn main() -> eyre::Result<()> {
         Ok(_) => tracing::info!("Commands set successfully"),
     };
 
+    let me = bot.get_me().await?;
+
+    let config = parse_config()?;
+
     let pool = SqlitePool::connect(&database_url).await?;
 
+    tokio::spawn(scheduler::run_scheduler(
+        bot.clone(),
+        pool.clone(),
+        config.clone(),
+    ));
+
     Dispatcher::builder(bot, schema())
-        .dependencies(dptree::deps![InMemStorage::<State>::new(), pool])
+        .dependencies(dptree::deps![
+            InMemStorage::<State>::new(),
+            pool,
+            me,
+            config
+        ])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
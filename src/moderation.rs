@@ -0,0 +1,123 @@
+use format as f;
+
+/// What a customer is allowed to do, changed by staff with `/ban`, `/restrict` and `/unban`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserStatus {
+    Active,
+    /// Can browse the store but not order.
+    Restricted,
+    /// Ignored by the bot entirely.
+    Banned,
+}
+
+impl UserStatus {
+    /// How the status is stored in `users.status`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UserStatus::Active => "active",
+            UserStatus::Restricted => "restricted",
+            UserStatus::Banned => "banned",
+        }
+    }
+
+    /// Reads a stored status. Unknown values count as active.
+    pub fn from_db(status: &str) -> UserStatus {
+        match status {
+            "restricted" => UserStatus::Restricted,
+            "banned" => UserStatus::Banned,
+            _ => UserStatus::Active,
+        }
+    }
+}
+
+/// A customer given to a staff command by id or by `@username`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserRef {
+    Id(i64),
+    Username(String),
+}
+
+impl std::fmt::Display for UserRef {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserRef::Id(id) => write!(formatter, "{id}"),
+            UserRef::Username(username) => write!(formatter, "@{username}"),
+        }
+    }
+}
+
+/// Parses command arguments such as `@username spamming` or `123456 chargebacks` into the
+/// customer and an optional reason.
+pub fn parse_user_ref(args: &str) -> Option<(UserRef, Option<String>)> {
+    let args = args.trim();
+    let (target, reason) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+
+    let user = match target.strip_prefix('@') {
+        Some(username) if !username.is_empty() => UserRef::Username(username.to_owned()),
+        Some(_) => return None,
+        None => UserRef::Id(target.parse().ok()?),
+    };
+
+    let reason = reason.trim();
+
+    Some((user, (!reason.is_empty()).then(|| reason.to_owned())))
+}
+
+/// Describes a status change for the customer it applies to.
+pub fn status_notice(status: UserStatus, reason: Option<&str>) -> String {
+    let notice = match status {
+        UserStatus::Active => "Your account is no longer restricted.",
+        UserStatus::Restricted => "Your account was restricted and can no longer place orders.",
+        UserStatus::Banned => "Your account was banned from the store.",
+    };
+
+    match reason {
+        Some(reason) if status != UserStatus::Active => f!("{notice} Reason: {reason}"),
+        _ => notice.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user_ref() {
+        assert_eq!(
+            parse_user_ref("@spammer  sends spam links "),
+            Some((
+                UserRef::Username("spammer".to_owned()),
+                Some("sends spam links".to_owned())
+            ))
+        );
+        assert_eq!(parse_user_ref("123456"), Some((UserRef::Id(123456), None)));
+        assert_eq!(parse_user_ref("@"), None);
+        assert_eq!(parse_user_ref("spammer"), None);
+        assert_eq!(parse_user_ref(""), None);
+    }
+
+    #[test]
+    fn test_user_status() {
+        for status in [
+            UserStatus::Active,
+            UserStatus::Restricted,
+            UserStatus::Banned,
+        ] {
+            assert_eq!(UserStatus::from_db(status.as_str()), status);
+        }
+
+        assert_eq!(UserStatus::from_db("unknown"), UserStatus::Active);
+    }
+
+    #[test]
+    fn test_status_notice() {
+        assert_eq!(
+            status_notice(UserStatus::Banned, Some("fraud")),
+            "Your account was banned from the store. Reason: fraud"
+        );
+        assert_eq!(
+            status_notice(UserStatus::Active, Some("appeal")),
+            "Your account is no longer restricted."
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::{fmt, str::FromStr};
+
+use format as f;
+use serde::{Deserialize, Serialize};
+
+/// An amount of money stored as a whole number of cents.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        self.0.checked_add(other.0).map(Money)
+    }
+
+    /// Multiplies a unit price by a quantity, failing instead of overflowing.
+    pub fn checked_mul(self, quantity: i64) -> Option<Money> {
+        self.0.checked_mul(quantity).map(Money)
+    }
+
+    /// Formats the amount as a plain decimal number such as `10.50`.
+    pub fn to_decimal_string(self) -> String {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+
+        f!("{sign}{}.{:02}", cents / 100, cents % 100)
+    }
+}
+
+/// How the store's currency is written, configured under `[currency]` in `Config.toml`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Currency {
+    /// ISO 4217 code recorded on every order, such as `USD` or `EUR`.
+    pub code: String,
+    pub symbol: String,
+    /// Writes `10,50 €` instead of `€10,50`.
+    pub symbol_after: bool,
+    pub decimal_separator: String,
+    pub thousands_separator: String,
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency {
+            code: "USD".to_owned(),
+            symbol: "$".to_owned(),
+            symbol_after: false,
+            decimal_separator: ".".to_owned(),
+            thousands_separator: ",".to_owned(),
+        }
+    }
+}
+
+impl Currency {
+    /// A neutral format for currencies other than the configured one, such as `10.50 GBP`.
+    pub fn plain(code: &str) -> Self {
+        Currency {
+            code: code.to_owned(),
+            symbol: code.to_owned(),
+            symbol_after: true,
+            ..Currency::default()
+        }
+    }
+}
+
+impl Money {
+    /// Formats the amount with the currency's symbol and separators, such as `$1,234.50`.
+    pub fn format(self, currency: &Currency) -> String {
+        let cents = self.0.unsigned_abs();
+        let units = (cents / 100).to_string();
+
+        let mut grouped = String::new();
+        for (index, digit) in units.chars().enumerate() {
+            if index > 0 && (units.len() - index).is_multiple_of(3) {
+                grouped.push_str(&currency.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let amount = f!("{grouped}{}{:02}", currency.decimal_separator, cents % 100);
+
+        if currency.symbol_after {
+            f!("{sign}{amount} {}", currency.symbol)
+        } else {
+            f!("{sign}{}{amount}", currency.symbol)
+        }
+    }
+
+    /// Parses admin input written in the store's currency, such as `€12,50` or `12,50 €`.
+    /// Thousands separators are only accepted between groups of three digits, so `12,50` is
+    /// rejected rather than read as `1250` when `,` separates thousands.
+    pub fn parse(input: &str, currency: &Currency) -> Result<Money, ParseMoneyError> {
+        let input = input.trim();
+        let input = input
+            .strip_prefix(currency.symbol.as_str())
+            .or_else(|| input.strip_suffix(currency.symbol.as_str()))
+            .unwrap_or(input)
+            .trim();
+
+        let (units, fraction) = match input.split_once(currency.decimal_separator.as_str()) {
+            Some((units, fraction)) => (units, Some(fraction)),
+            None => (input, None),
+        };
+
+        let separator = currency.thousands_separator.as_str();
+        let units = if !separator.is_empty() && units.contains(separator) {
+            let mut groups = units.split(separator);
+            let first = groups.next().unwrap_or_default();
+
+            if first.is_empty() || first.len() > 3 || groups.any(|group| group.len() != 3) {
+                return Err(ParseMoneyError);
+            }
+
+            units.replace(separator, "")
+        } else {
+            units.to_owned()
+        };
+
+        match fraction {
+            Some(fraction) => f!("{units}.{fraction}").parse(),
+            None => units.parse(),
+        }
+    }
+}
+
+/// Written in `Config.toml` as a decimal string such as `"9.99"`.
+impl Serialize for Money {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_decimal_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseMoneyError;
+
+impl fmt::Display for ParseMoneyError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("expected a non-negative amount such as 12.50")
+    }
+}
+
+impl std::error::Error for ParseMoneyError {}
+
+/// Parses admin input such as `12`, `12.5`, `12.50` or `$12.50`.
+impl FromStr for Money {
+    type Err = ParseMoneyError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let input = input.strip_prefix('$').unwrap_or(input).trim_start();
+
+        let (units, fraction) = input.split_once('.').unwrap_or((input, ""));
+
+        let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if units.is_empty() || !is_digits(units) || fraction.len() > 2 || !is_digits(fraction) {
+            return Err(ParseMoneyError);
+        }
+
+        let units = units.parse::<i64>().map_err(|_| ParseMoneyError)?;
+        let fraction = f!("{fraction:0<2}")
+            .parse::<i64>()
+            .map_err(|_| ParseMoneyError)?;
+
+        units
+            .checked_mul(100)
+            .and_then(|cents| cents.checked_add(fraction))
+            .map(Money)
+            .ok_or(ParseMoneyError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn euro() -> Currency {
+        Currency {
+            code: "EUR".to_owned(),
+            symbol: "€".to_owned(),
+            symbol_after: true,
+            decimal_separator: ",".to_owned(),
+            thousands_separator: ".".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let dollar = Currency::default();
+
+        assert_eq!(Money::from_cents(1050).format(&dollar), "$10.50");
+        assert_eq!(Money::from_cents(5).format(&dollar), "$0.05");
+        assert_eq!(Money::from_cents(-250).format(&dollar), "-$2.50");
+        assert_eq!(Money::ZERO.format(&dollar), "$0.00");
+        assert_eq!(
+            Money::from_cents(123456789).format(&dollar),
+            "$1,234,567.89"
+        );
+
+        assert_eq!(Money::from_cents(123450).format(&euro()), "1.234,50 €");
+        assert_eq!(
+            Money::from_cents(1050).format(&Currency::plain("GBP")),
+            "10.50 GBP"
+        );
+    }
+
+    #[test]
+    fn test_to_decimal_string() {
+        assert_eq!(Money::from_cents(123450).to_decimal_string(), "1234.50");
+        assert_eq!(Money::from_cents(-5).to_decimal_string(), "-0.05");
+    }
+
+    #[test]
+    fn test_parse_with_currency() {
+        assert_eq!(
+            Money::parse("12,50 €", &euro()),
+            Ok(Money::from_cents(1250))
+        );
+        assert_eq!(
+            Money::parse("€1.234,5", &euro()),
+            Ok(Money::from_cents(123450))
+        );
+        assert_eq!(
+            Money::parse("$1,234.50", &Currency::default()),
+            Ok(Money::from_cents(123450))
+        );
+        assert_eq!(Money::parse("12,5,0", &euro()), Err(ParseMoneyError));
+    }
+
+    #[test]
+    fn test_parse_ambiguous_separators() {
+        let dollar = Currency::default();
+
+        assert_eq!(Money::parse("12,50", &dollar), Err(ParseMoneyError));
+        assert_eq!(Money::parse("1,5", &dollar), Err(ParseMoneyError));
+        assert_eq!(Money::parse("1,2345.00", &dollar), Err(ParseMoneyError));
+        assert_eq!(Money::parse(",500", &dollar), Err(ParseMoneyError));
+        assert_eq!(Money::parse("1.234,50", &dollar), Err(ParseMoneyError));
+        assert_eq!(
+            Money::parse("1,250", &dollar),
+            Ok(Money::from_cents(125000))
+        );
+        assert_eq!(
+            Money::parse("1,234,567.89", &dollar),
+            Ok(Money::from_cents(123456789))
+        );
+
+        assert_eq!(Money::parse("12.50", &euro()), Err(ParseMoneyError));
+        assert_eq!(Money::parse("1.5", &euro()), Err(ParseMoneyError));
+        assert_eq!(Money::parse("1,234.50", &euro()), Err(ParseMoneyError));
+        assert_eq!(
+            Money::parse("1.250", &euro()),
+            Ok(Money::from_cents(125000))
+        );
+        assert_eq!(Money::parse("12,5", &euro()), Ok(Money::from_cents(1250)));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("12.50".parse(), Ok(Money::from_cents(1250)));
+        assert_eq!("$12.50".parse(), Ok(Money::from_cents(1250)));
+        assert_eq!(" 12.5 ".parse(), Ok(Money::from_cents(1250)));
+        assert_eq!("12".parse(), Ok(Money::from_cents(1200)));
+        assert_eq!("0.07".parse(), Ok(Money::from_cents(7)));
+
+        assert_eq!("".parse::<Money>(), Err(ParseMoneyError));
+        assert_eq!(".50".parse::<Money>(), Err(ParseMoneyError));
+        assert_eq!("-1".parse::<Money>(), Err(ParseMoneyError));
+        assert_eq!("1.234".parse::<Money>(), Err(ParseMoneyError));
+        assert_eq!("1,50".parse::<Money>(), Err(ParseMoneyError));
+        assert_eq!(
+            "99999999999999999999".parse::<Money>(),
+            Err(ParseMoneyError)
+        );
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let price = Money::from_cents(1050);
+
+        assert_eq!(price.checked_mul(3), Some(Money::from_cents(3150)));
+        assert_eq!(Money::from_cents(i64::MAX).checked_mul(2), None);
+
+        assert_eq!(price.checked_add(price), Some(Money::from_cents(2100)));
+        assert_eq!(Money::from_cents(i64::MAX).checked_add(price), None);
+    }
+}
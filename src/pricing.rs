@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+/// Whether catalog prices already include tax or have it added on top.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxMode {
+    #[default]
+    Exclusive,
+    Inclusive,
+}
+
+/// Tax rates configured under `[tax]` in `Config.toml`, as percentages such as `8.25`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaxConfig {
+    pub mode: TaxMode,
+    /// Store-wide rate, used for products without a category rate.
+    pub rate: f64,
+    /// Rates per product category, overriding the store-wide rate.
+    pub categories: HashMap<String, f64>,
+}
+
+impl TaxConfig {
+    /// The rate for a product in basis points, so that tax is computed on integers.
+    fn rate_bps(&self, category: Option<&str>) -> i128 {
+        let rate = category
+            .and_then(|category| self.categories.get(category))
+            .unwrap_or(&self.rate);
+
+        (rate * 100.0).round() as i128
+    }
+}
+
+/// Fees and limits for a fulfillment method, configured under `[fulfillment.<METHOD>]` in
+/// `Config.toml` with amounts written as decimal strings.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FulfillmentConfig {
+    pub fee: Money,
+    /// Smallest order, after promotions and discounts, accepted for this method.
+    pub minimum_order: Money,
+    /// Orders worth at least this much, after promotions and discounts, pay no fee.
+    pub free_over: Option<Money>,
+    /// Whether orders need one of the customer's saved addresses and their phone number.
+    pub requires_address: bool,
+}
+
+impl FulfillmentConfig {
+    /// The fee for an order worth `amount` after promotions and discounts.
+    pub fn fee_for(&self, amount: Money) -> Money {
+        match self.free_over {
+            Some(free_over) if amount >= free_over => Money::ZERO,
+            _ => self.fee,
+        }
+    }
+}
+
+/// A cart or order line as priced by the store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub product_id: i64,
+    pub name: String,
+    pub category: Option<String>,
+    pub unit_price: Money,
+    pub quantity: i64,
+    /// Promotions applied to the line, see [`crate::promotions`].
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// An amount taken off a line, with the name of the promotion explaining it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustment {
+    pub name: String,
+    pub amount: Money,
+}
+
+impl Line {
+    /// The line's amount before promotions.
+    pub fn gross(&self) -> Option<Money> {
+        self.unit_price.checked_mul(self.quantity)
+    }
+
+    pub fn discount(&self) -> Option<Money> {
+        self.adjustments
+            .iter()
+            .try_fold(Money::ZERO, |total, adjustment| {
+                total.checked_add(adjustment.amount)
+            })
+    }
+
+    /// The line's amount after promotions.
+    pub fn amount(&self) -> Option<Money> {
+        Some(Money::from_cents(
+            self.gross()?.cents() - self.discount()?.cents(),
+        ))
+    }
+}
+
+/// A discount taken off the subtotal, such as a coupon's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discount {
+    /// Whole percent of the subtotal, from 1 to 100.
+    Percent(i64),
+    Fixed(Money),
+}
+
+impl Discount {
+    /// The amount taken off `subtotal`, never more than the subtotal itself.
+    pub fn amount(&self, subtotal: Money) -> Money {
+        let amount = match self {
+            Discount::Percent(percent) => {
+                Money::from_cents(div_round(subtotal.cents() as i128 * *percent as i128, 100) as i64)
+            }
+            Discount::Fixed(amount) => *amount,
+        };
+
+        amount.min(subtotal).max(Money::ZERO)
+    }
+}
+
+/// Totals of a set of lines. With inclusive tax the subtotal already contains the tax.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// The lines' amounts before promotions.
+    pub subtotal: Money,
+    pub promotions: Money,
+    /// The coupon discount, taken off the subtotal after promotions.
+    pub discount: Money,
+    pub tax: Money,
+    /// The fulfillment fee, which is not taxed.
+    pub fee: Money,
+    /// What redeemed loyalty points took off the total.
+    pub points: Money,
+    pub total: Money,
+    pub tax_included: bool,
+}
+
+impl Summary {
+    /// What the customer pays for the lines before the coupon and tax.
+    pub fn after_promotions(&self) -> Money {
+        Money::from_cents(self.subtotal.cents() - self.promotions.cents())
+    }
+
+    /// What the customer pays for the lines before tax and fees.
+    pub fn after_discounts(&self) -> Money {
+        Money::from_cents(self.after_promotions().cents() - self.discount.cents())
+    }
+
+    /// Adds the fee for `fulfillment` to the total, returning `None` if it overflows.
+    pub fn with_fee(self, fulfillment: &FulfillmentConfig) -> Option<Summary> {
+        let fee = fulfillment.fee_for(self.after_discounts());
+
+        Some(Summary {
+            fee,
+            total: self.total.checked_add(fee)?,
+            ..self
+        })
+    }
+
+    /// Takes the value of redeemed loyalty points off the total.
+    pub fn with_points(self, points: Money) -> Summary {
+        Summary {
+            points,
+            total: Money::from_cents(self.total.cents() - points.cents()),
+            ..self
+        }
+    }
+}
+
+/// Computes subtotal, promotions, discount, tax and total, returning `None` if any amount
+/// overflows. Tax is charged on the discounted amount.
+pub fn summarize(lines: &[Line], tax: &TaxConfig, discount: Option<&Discount>) -> Option<Summary> {
+    let mut subtotal = Money::ZERO;
+    let mut promotions = Money::ZERO;
+    let mut total_tax = Money::ZERO;
+
+    for line in lines {
+        let amount = line.amount()?;
+        let rate = tax.rate_bps(line.category.as_deref());
+
+        subtotal = subtotal.checked_add(line.gross()?)?;
+        promotions = promotions.checked_add(line.discount()?)?;
+        total_tax = total_tax.checked_add(line_tax(amount, rate, tax.mode)?)?;
+    }
+
+    let net = Money::from_cents(subtotal.cents() - promotions.cents());
+    let discount = discount.map_or(Money::ZERO, |discount| discount.amount(net));
+    let discounted = Money::from_cents(net.cents() - discount.cents());
+
+    // The discount is spread over the lines in proportion to their amounts.
+    if discount > Money::ZERO {
+        total_tax = Money::from_cents(div_round(
+            total_tax.cents() as i128 * discounted.cents() as i128,
+            net.cents() as i128,
+        ) as i64);
+    }
+
+    let total = match tax.mode {
+        TaxMode::Exclusive => discounted.checked_add(total_tax)?,
+        TaxMode::Inclusive => discounted,
+    };
+
+    Some(Summary {
+        subtotal,
+        promotions,
+        discount,
+        tax: total_tax,
+        fee: Money::ZERO,
+        points: Money::ZERO,
+        total,
+        tax_included: tax.mode == TaxMode::Inclusive,
+    })
+}
+
+/// Splits `total` in proportion to `shares`, such as each person's subtotal of a shared order.
+/// The parts always add up to `total`, with any rounding left to the last one.
+pub fn split_total(total: Money, shares: &[Money]) -> Vec<Money> {
+    let whole = shares
+        .iter()
+        .map(|share| share.cents() as i128)
+        .sum::<i128>();
+    let mut remaining = total.cents() as i128;
+
+    shares
+        .iter()
+        .enumerate()
+        .map(|(index, share)| {
+            let part = if index + 1 == shares.len() {
+                remaining
+            } else if whole > 0 {
+                div_round(total.cents() as i128 * share.cents() as i128, whole)
+            } else {
+                0
+            };
+            remaining -= part;
+
+            Money::from_cents(part as i64)
+        })
+        .collect()
+}
+
+/// Tax on one line, rounded half up to the cent.
+fn line_tax(amount: Money, rate_bps: i128, mode: TaxMode) -> Option<Money> {
+    let amount = amount.cents() as i128;
+
+    let tax = match mode {
+        TaxMode::Exclusive => div_round(amount * rate_bps, 10_000),
+        TaxMode::Inclusive => amount - div_round(amount * 10_000, 10_000 + rate_bps),
+    };
+
+    i64::try_from(tax).ok().map(Money::from_cents)
+}
+
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    (numerator * 2 + denominator).div_euclid(denominator * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(category: Option<&str>, unit_price: i64, quantity: i64) -> Line {
+        Line {
+            product_id: 1,
+            name: "Runtz".to_owned(),
+            category: category.map(ToOwned::to_owned),
+            unit_price: Money::from_cents(unit_price),
+            quantity,
+            adjustments: vec![],
+        }
+    }
+
+    #[test]
+    fn test_exclusive_tax() {
+        let tax = TaxConfig {
+            mode: TaxMode::Exclusive,
+            rate: 8.25,
+            categories: HashMap::from([("food".to_owned(), 0.0)]),
+        };
+
+        let summary = summarize(
+            &[line(None, 1000, 2), line(Some("food"), 500, 1)],
+            &tax,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(summary.subtotal, Money::from_cents(2500));
+        assert_eq!(summary.tax, Money::from_cents(165));
+        assert_eq!(summary.total, Money::from_cents(2665));
+        assert!(!summary.tax_included);
+    }
+
+    #[test]
+    fn test_inclusive_tax() {
+        let tax = TaxConfig {
+            mode: TaxMode::Inclusive,
+            rate: 20.0,
+            categories: HashMap::new(),
+        };
+
+        let summary = summarize(&[line(None, 1200, 1)], &tax, None).unwrap();
+
+        assert_eq!(summary.subtotal, Money::from_cents(1200));
+        assert_eq!(summary.tax, Money::from_cents(200));
+        assert_eq!(summary.total, Money::from_cents(1200));
+        assert!(summary.tax_included);
+    }
+
+    #[test]
+    fn test_tax_rounds_half_up() {
+        let tax = TaxConfig {
+            mode: TaxMode::Exclusive,
+            rate: 5.0,
+            categories: HashMap::new(),
+        };
+
+        // 5% of 0.10 is 0.005, which rounds up to a cent.
+        assert_eq!(
+            summarize(&[line(None, 10, 1)], &tax, None).unwrap().tax,
+            Money::from_cents(1)
+        );
+    }
+
+    #[test]
+    fn test_discount() {
+        let subtotal = Money::from_cents(2500);
+
+        assert_eq!(
+            Discount::Percent(10).amount(subtotal),
+            Money::from_cents(250)
+        );
+        assert_eq!(
+            Discount::Fixed(Money::from_cents(500)).amount(subtotal),
+            Money::from_cents(500)
+        );
+        assert_eq!(
+            Discount::Fixed(Money::from_cents(5000)).amount(subtotal),
+            subtotal
+        );
+    }
+
+    #[test]
+    fn test_discount_reduces_tax() {
+        let tax = TaxConfig {
+            mode: TaxMode::Exclusive,
+            rate: 10.0,
+            categories: HashMap::new(),
+        };
+
+        let summary =
+            summarize(&[line(None, 2000, 1)], &tax, Some(&Discount::Percent(25))).unwrap();
+
+        assert_eq!(summary.subtotal, Money::from_cents(2000));
+        assert_eq!(summary.discount, Money::from_cents(500));
+        assert_eq!(summary.tax, Money::from_cents(150));
+        assert_eq!(summary.total, Money::from_cents(1650));
+    }
+
+    #[test]
+    fn test_promotions_reduce_tax_and_coupon_base() {
+        let tax = TaxConfig {
+            mode: TaxMode::Exclusive,
+            rate: 10.0,
+            categories: HashMap::new(),
+        };
+
+        let mut promoted = line(None, 1000, 3);
+        promoted.adjustments.push(Adjustment {
+            name: "Buy 2 get 1 free".to_owned(),
+            amount: Money::from_cents(1000),
+        });
+
+        let summary = summarize(&[promoted], &tax, Some(&Discount::Percent(50))).unwrap();
+
+        assert_eq!(summary.subtotal, Money::from_cents(3000));
+        assert_eq!(summary.promotions, Money::from_cents(1000));
+        assert_eq!(summary.after_promotions(), Money::from_cents(2000));
+        assert_eq!(summary.discount, Money::from_cents(1000));
+        assert_eq!(summary.tax, Money::from_cents(100));
+        assert_eq!(summary.total, Money::from_cents(1100));
+    }
+
+    #[test]
+    fn test_fulfillment_fee() {
+        let delivery = FulfillmentConfig {
+            fee: Money::from_cents(500),
+            minimum_order: Money::from_cents(2000),
+            free_over: Some(Money::from_cents(5000)),
+            requires_address: true,
+        };
+
+        let summary = summarize(&[line(None, 2500, 1)], &TaxConfig::default(), None)
+            .unwrap()
+            .with_fee(&delivery)
+            .unwrap();
+        assert_eq!(summary.fee, Money::from_cents(500));
+        assert_eq!(summary.total, Money::from_cents(3000));
+
+        // The threshold applies to the amount after the coupon.
+        let summary = summarize(
+            &[line(None, 6000, 1)],
+            &TaxConfig::default(),
+            Some(&Discount::Percent(50)),
+        )
+        .unwrap()
+        .with_fee(&delivery)
+        .unwrap();
+        assert_eq!(summary.fee, Money::from_cents(500));
+
+        assert_eq!(delivery.fee_for(Money::from_cents(5000)), Money::ZERO);
+        assert_eq!(
+            FulfillmentConfig::default().fee_for(Money::from_cents(100)),
+            Money::ZERO
+        );
+    }
+
+    #[test]
+    fn test_split_total() {
+        let cents = |parts: Vec<Money>| parts.iter().map(|part| part.cents()).collect::<Vec<_>>();
+
+        assert_eq!(
+            cents(split_total(
+                Money::from_cents(1000),
+                &[
+                    Money::from_cents(100),
+                    Money::from_cents(100),
+                    Money::from_cents(100)
+                ]
+            )),
+            vec![333, 333, 334]
+        );
+        assert_eq!(
+            cents(split_total(
+                Money::from_cents(1100),
+                &[Money::from_cents(3000), Money::from_cents(1000)]
+            )),
+            vec![825, 275]
+        );
+        assert_eq!(
+            cents(split_total(
+                Money::from_cents(500),
+                &[Money::ZERO, Money::ZERO]
+            )),
+            vec![0, 500]
+        );
+        assert!(split_total(Money::from_cents(500), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_summarize_overflow() {
+        assert_eq!(
+            summarize(&[line(None, i64::MAX, 2)], &TaxConfig::default(), None),
+            None
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::pricing::{Adjustment, Discount, Line};
+
+/// A promotion applied automatically to every cart, configured as `[[promotions]]` in
+/// `Config.toml`. The `name` is shown to customers next to the lines it discounts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Promotion {
+    /// Out of every `buy + get` units of a product, `get` are free.
+    BuyXGetY {
+        name: String,
+        product_id: i64,
+        buy: i64,
+        get: i64,
+    },
+    /// One of each listed product for a fixed price. List a product twice to require two.
+    Bundle {
+        name: String,
+        product_ids: Vec<i64>,
+        price: Money,
+    },
+    /// A percentage off lines of at least `min_quantity` units, limited to a product or a
+    /// category if given.
+    QuantityTier {
+        name: String,
+        #[serde(default)]
+        product_id: Option<i64>,
+        #[serde(default)]
+        category: Option<String>,
+        min_quantity: i64,
+        percent: i64,
+    },
+    /// A percentage off during the last `minutes` before the store's `close` time, limited to
+    /// a category if given.
+    HappyHour {
+        name: String,
+        minutes: i64,
+        percent: i64,
+        #[serde(default)]
+        category: Option<String>,
+    },
+}
+
+/// The time promotions are evaluated at, and the store's closing time for happy hours.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    pub now: NaiveTime,
+    pub close: Option<NaiveTime>,
+}
+
+/// Applies the promotions in order, returning the lines with an [`Adjustment`] for each
+/// promotion that discounted them. A line is never discounted below zero.
+pub fn apply_promotions(lines: &[Line], promotions: &[Promotion], clock: Clock) -> Vec<Line> {
+    let mut lines = lines.to_vec();
+
+    for promotion in promotions {
+        match promotion {
+            Promotion::BuyXGetY {
+                name,
+                product_id,
+                buy,
+                get,
+            } => {
+                if *buy < 1 || *get < 1 {
+                    continue;
+                }
+
+                for line in lines
+                    .iter_mut()
+                    .filter(|line| line.product_id == *product_id)
+                {
+                    let free = line.quantity / (buy + get) * get;
+                    if let Some(amount) = line.unit_price.checked_mul(free) {
+                        adjust(line, name, amount);
+                    }
+                }
+            }
+
+            Promotion::Bundle {
+                name,
+                product_ids,
+                price,
+            } => apply_bundle(&mut lines, name, product_ids, *price),
+
+            Promotion::QuantityTier {
+                name,
+                product_id,
+                category,
+                min_quantity,
+                percent,
+            } => {
+                for line in lines.iter_mut().filter(|line| {
+                    line.quantity >= *min_quantity
+                        && product_id.is_none_or(|product_id| line.product_id == product_id)
+                        && in_category(line, category)
+                }) {
+                    adjust_percent(line, name, *percent);
+                }
+            }
+
+            Promotion::HappyHour {
+                name,
+                minutes,
+                percent,
+                category,
+            } => {
+                if !is_happy_hour(clock, *minutes) {
+                    continue;
+                }
+
+                for line in lines.iter_mut().filter(|line| in_category(line, category)) {
+                    adjust_percent(line, name, *percent);
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+fn apply_bundle(lines: &mut [Line], name: &str, product_ids: &[i64], price: Money) {
+    let mut required = BTreeMap::<i64, i64>::new();
+    for product_id in product_ids {
+        *required.entry(*product_id).or_default() += 1;
+    }
+
+    // How many complete bundles the cart holds, and the regular price of one.
+    let mut bundles = i64::MAX;
+    let mut regular = 0i128;
+    for (product_id, count) in &required {
+        match lines.iter().find(|line| line.product_id == *product_id) {
+            Some(line) => {
+                bundles = bundles.min(line.quantity / count);
+                regular += line.unit_price.cents() as i128 * *count as i128;
+            }
+            None => return,
+        }
+    }
+
+    let savings = (regular - price.cents() as i128) * bundles as i128;
+    if required.is_empty() || bundles == 0 || savings <= 0 {
+        return;
+    }
+
+    // The savings are split over the bundled lines in proportion to their regular prices.
+    let mut remaining = savings;
+    let mut bundled = required.iter().peekable();
+    while let Some((product_id, count)) = bundled.next() {
+        let line = match lines.iter_mut().find(|line| line.product_id == *product_id) {
+            Some(line) => line,
+            None => return,
+        };
+
+        let share = if bundled.peek().is_some() {
+            savings * line.unit_price.cents() as i128 * *count as i128 / regular
+        } else {
+            remaining
+        };
+        remaining -= share;
+
+        if let Ok(share) = i64::try_from(share) {
+            adjust(line, name, Money::from_cents(share));
+        }
+    }
+}
+
+fn in_category(line: &Line, category: &Option<String>) -> bool {
+    category.is_none() || line.category == *category
+}
+
+fn is_happy_hour(clock: Clock, minutes: i64) -> bool {
+    let close = match clock.close {
+        Some(close) if minutes > 0 => close,
+        _ => return false,
+    };
+
+    let start = close - Duration::minutes(minutes.min(24 * 60));
+
+    // The window may wrap around midnight when the store closes early in the morning.
+    if start <= close {
+        start <= clock.now && clock.now < close
+    } else {
+        clock.now >= start || clock.now < close
+    }
+}
+
+fn adjust_percent(line: &mut Line, name: &str, percent: i64) {
+    if let Some(amount) = line.amount() {
+        adjust(line, name, Discount::Percent(percent).amount(amount));
+    }
+}
+
+/// Takes `amount` off the line, capped at what is left of it.
+fn adjust(line: &mut Line, name: &str, amount: Money) {
+    let amount = match line.amount() {
+        Some(remaining) => amount.min(remaining),
+        None => return,
+    };
+
+    if amount > Money::ZERO {
+        line.adjustments.push(Adjustment {
+            name: name.to_owned(),
+            amount,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(product_id: i64, category: Option<&str>, unit_price: i64, quantity: i64) -> Line {
+        Line {
+            product_id,
+            name: format!("Product {product_id}"),
+            category: category.map(ToOwned::to_owned),
+            unit_price: Money::from_cents(unit_price),
+            quantity,
+            adjustments: vec![],
+        }
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    fn clock(now: &str) -> Clock {
+        Clock {
+            now: time(now),
+            close: Some(time("22:00")),
+        }
+    }
+
+    fn discounts(lines: &[Line]) -> Vec<i64> {
+        lines
+            .iter()
+            .map(|line| line.discount().unwrap().cents())
+            .collect()
+    }
+
+    #[test]
+    fn test_buy_x_get_y() {
+        let promotion = Promotion::BuyXGetY {
+            name: "Buy 2 get 1 free".to_owned(),
+            product_id: 1,
+            buy: 2,
+            get: 1,
+        };
+
+        let lines = apply_promotions(
+            &[line(1, None, 1000, 7), line(2, None, 1000, 3)],
+            &[promotion],
+            clock("12:00"),
+        );
+
+        assert_eq!(discounts(&lines), vec![2000, 0]);
+        assert_eq!(lines[0].adjustments[0].name, "Buy 2 get 1 free");
+    }
+
+    #[test]
+    fn test_bundle() {
+        let promotion = Promotion::Bundle {
+            name: "Combo".to_owned(),
+            product_ids: vec![1, 2, 2],
+            price: Money::from_cents(2000),
+        };
+
+        // Two bundles of 1000 + 2 * 750 for 2000 each save 500 per bundle.
+        let lines = apply_promotions(
+            &[line(1, None, 1000, 3), line(2, None, 750, 4)],
+            std::slice::from_ref(&promotion),
+            clock("12:00"),
+        );
+        assert_eq!(discounts(&lines).iter().sum::<i64>(), 1000);
+
+        // An incomplete bundle is not discounted.
+        let lines = apply_promotions(&[line(1, None, 1000, 3)], &[promotion], clock("12:00"));
+        assert_eq!(discounts(&lines), vec![0]);
+    }
+
+    #[test]
+    fn test_quantity_tier() {
+        let promotion = Promotion::QuantityTier {
+            name: "10% off 5 or more".to_owned(),
+            product_id: None,
+            category: Some("flower".to_owned()),
+            min_quantity: 5,
+            percent: 10,
+        };
+
+        let lines = apply_promotions(
+            &[
+                line(1, Some("flower"), 1000, 5),
+                line(2, Some("flower"), 1000, 4),
+                line(3, Some("edibles"), 1000, 5),
+            ],
+            &[promotion],
+            clock("12:00"),
+        );
+
+        assert_eq!(discounts(&lines), vec![500, 0, 0]);
+    }
+
+    #[test]
+    fn test_happy_hour() {
+        let promotion = Promotion::HappyHour {
+            name: "Happy hour".to_owned(),
+            minutes: 60,
+            percent: 20,
+            category: None,
+        };
+        let lines = [line(1, None, 1000, 1)];
+
+        let apply = |clock| {
+            discounts(&apply_promotions(
+                &lines,
+                std::slice::from_ref(&promotion),
+                clock,
+            ))
+        };
+
+        assert_eq!(apply(clock("21:00")), vec![200]);
+        assert_eq!(apply(clock("20:59")), vec![0]);
+        assert_eq!(apply(clock("22:00")), vec![0]);
+        assert_eq!(
+            apply(Clock {
+                now: time("00:30"),
+                close: Some(time("01:00")),
+            }),
+            vec![200]
+        );
+        assert_eq!(
+            apply(Clock {
+                now: time("21:30"),
+                close: None,
+            }),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_promotions_never_exceed_the_line() {
+        let promotions = [
+            Promotion::BuyXGetY {
+                name: "Buy 1 get 1 free".to_owned(),
+                product_id: 1,
+                buy: 1,
+                get: 1,
+            },
+            Promotion::Bundle {
+                name: "Bundle".to_owned(),
+                product_ids: vec![1],
+                price: Money::ZERO,
+            },
+            Promotion::QuantityTier {
+                name: "Tier".to_owned(),
+                product_id: Some(1),
+                category: None,
+                min_quantity: 1,
+                percent: 50,
+            },
+        ];
+
+        let lines = apply_promotions(&[line(1, None, 1000, 2)], &promotions, clock("12:00"));
+
+        assert_eq!(discounts(&lines), vec![2000]);
+        assert_eq!(lines[0].amount(), Some(Money::ZERO));
+        assert_eq!(lines[0].adjustments.len(), 2);
+    }
+}
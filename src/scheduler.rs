@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use format as f;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::commands::wishlist::notify_wishlists;
+use crate::utils::Config;
+
+/// Abandoned cart reminders, configured under `[reminders]` in `Config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReminderConfig {
+    pub enabled: bool,
+    /// How long a cart must be left untouched before its owner is reminded once.
+    pub remind_after_hours: i64,
+    /// How long a cart must be left untouched before it is cleared. 0 keeps carts forever.
+    pub expire_after_hours: i64,
+    /// How often carts are checked.
+    pub interval_minutes: u64,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        ReminderConfig {
+            enabled: false,
+            remind_after_hours: 24,
+            expire_after_hours: 168,
+            interval_minutes: 15,
+        }
+    }
+}
+
+/// Wishlist notifications, configured under `[wishlist]` in `Config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WishlistConfig {
+    /// How often saved products are checked for restocks and price drops.
+    pub interval_minutes: u64,
+}
+
+impl Default for WishlistConfig {
+    fn default() -> Self {
+        WishlistConfig {
+            interval_minutes: 1,
+        }
+    }
+}
+
+/// Runs the bot's periodic jobs until the process exits.
+pub async fn run_scheduler(bot: Bot, pool: SqlitePool, config: Config) {
+    tokio::join!(
+        run_cart_jobs(&bot, &pool, &config),
+        run_wishlist_jobs(&bot, &pool, &config)
+    );
+}
+
+async fn run_wishlist_jobs(bot: &Bot, pool: &SqlitePool, config: &Config) {
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        config.wishlist.interval_minutes.max(1) * 60,
+    ));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = notify_wishlists(bot, pool, config).await {
+            tracing::error!("Failed to send wishlist notifications: {}", err);
+        }
+    }
+}
+
+async fn run_cart_jobs(bot: &Bot, pool: &SqlitePool, config: &Config) {
+    if !config.reminders.enabled {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        config.reminders.interval_minutes.max(1) * 60,
+    ));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = expire_carts(pool, config).await {
+            tracing::error!("Failed to expire carts: {}", err);
+        }
+
+        // Reminders are only sent while the store is open, so customers can act on them.
+        if config.is_open(chrono::Local::now().time()) {
+            if let Err(err) = remind_abandoned_carts(bot, pool, config).await {
+                tracing::error!("Failed to send cart reminders: {}", err);
+            }
+        }
+    }
+}
+
+async fn expire_carts(pool: &SqlitePool, config: &Config) -> Result<(), sqlx::Error> {
+    if config.reminders.expire_after_hours <= 0 {
+        return Ok(());
+    }
+
+    let expire_after = f!("-{} hours", config.reminders.expire_after_hours);
+
+    let mut tx = pool.begin().await?;
+
+    // Coupons go first, since deleting the items marks the carts as updated.
+    sqlx::query!(
+        "UPDATE carts SET coupon_id = NULL
+        WHERE coupon_id IS NOT NULL AND updated_at < datetime('now', ?)",
+        expire_after
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let expired = sqlx::query!(
+        "DELETE FROM cart_items WHERE cart_id IN
+            (SELECT id FROM carts WHERE updated_at < datetime('now', ?))",
+        expire_after
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if expired.rows_affected() > 0 {
+        tracing::info!("cleared {} expired cart items", expired.rows_affected());
+    }
+
+    Ok(())
+}
+
+async fn remind_abandoned_carts(
+    bot: &Bot,
+    pool: &SqlitePool,
+    config: &Config,
+) -> Result<(), sqlx::Error> {
+    let remind_after = f!("-{} hours", config.reminders.remind_after_hours);
+
+    let carts = sqlx::query!(
+        r#"SELECT carts.id AS "id!", carts.user_id AS "user_id!",
+            (SELECT SUM(quantity) FROM cart_items WHERE cart_id = carts.id) AS "units!: i64"
+        FROM carts
+        INNER JOIN users ON carts.user_id = users.id
+        WHERE users.cart_reminders
+            AND users.status != 'banned'
+            AND carts.reminded_at IS NULL
+            AND carts.updated_at < datetime('now', ?)
+            AND EXISTS (SELECT 1 FROM cart_items WHERE cart_id = carts.id)"#,
+        remind_after
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for cart in carts {
+        let sent = bot
+            .send_message(
+                ChatId(cart.user_id),
+                f!(
+                    "You left {} items in your cart. Complete your order before they're gone!",
+                    cart.units
+                ),
+            )
+            .reply_markup(InlineKeyboardMarkup::new([vec![
+                InlineKeyboardButton::callback("View Cart", "view_cart"),
+                InlineKeyboardButton::callback("Stop Reminders", "cart_reminders off"),
+            ]]))
+            .await;
+
+        // A failed reminder, e.g. because the bot was blocked, is not retried.
+        if let Err(err) = sent {
+            tracing::warn!("Failed to remind user {}: {}", cart.user_id, err);
+        }
+
+        sqlx::query!(
+            "UPDATE carts SET reminded_at = CURRENT_TIMESTAMP WHERE id = ?",
+            cart.id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
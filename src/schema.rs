@@ -8,6 +8,7 @@ use teloxide::{
     utils::command::BotCommands,
 };
 
+use crate::catalog::CatalogRow;
 use crate::commands::{
     add::{
         add_product, receive_product_description, receive_product_image, receive_product_name,
@@ -15,17 +16,37 @@ use crate::commands::{
     },
     cancel::cancel,
     cart::{
-        edit_cart_item_quantity_callback, place_order_callback,
-        receive_edit_cart_item_quantity_amount, receive_edit_cart_item_quantity_id,
-        receive_remove_cart_item_id, remove_cart_item_callback, view_cart,
+        cart_address_callback, cart_item_callback, fulfillment_method_callback,
+        place_order_callback, receive_delivery_location, share_location_callback,
+        use_points_callback, view_cart, view_cart_callback,
     },
+    catalog::{export_catalog, import_catalog_callback, receive_catalog_document},
+    coupons::{apply_coupon_callback, coupon, receive_coupon_code, remove_coupon_callback},
+    edit::{
+        edit_product, edit_product_callback, receive_edit_product_field, receive_edit_product_id,
+        ProductField,
+    },
+    gallery::{gallery, gallery_callback, receive_gallery_image, receive_gallery_product_id},
     help::help,
-    inventory::{add_to_cart_callback, inventory, view_product_callback},
-    orders::view_orders,
-    remove::{receive_product_id, remove_product},
+    inline::inline_query,
+    inventory::{
+        add_to_cart_callback, inventory, view_product_callback, view_product_image_callback,
+    },
+    moderation::{ban, is_not_banned, restrict, unban},
+    orders::{cancel_order, fulfill_order, split_bill_callback, view_order_callback, view_orders},
+    points::points,
+    profile::{
+        profile, profile_callback, receive_address, receive_address_label, receive_profile_phone,
+        remove_address_callback,
+    },
+    reminders::{cart_reminders_callback, reminders},
+    remove::{receive_product_id, remove_product, restore_product, restore_product_callback},
     shop::shop,
-    start::start,
+    start::{register_user, start},
+    wishlist::{save_product_callback, wishlist, wishlist_remove_callback},
 };
+use crate::money::Money;
+use crate::utils::Config;
 
 pub type AppDialogue = Dialogue<State, InMemStorage<State>>;
 
@@ -48,17 +69,39 @@ pub enum State {
     ReceiveProductImage {
         product_name: String,
         product_description: String,
-        product_price: i64,
+        product_price: Money,
+    },
+
+    // Edit product
+    ReceiveEditProductId,
+    ReceiveEditProductField {
+        product_id: i64,
+        field: ProductField,
     },
 
     // Remove product
     ReceiveProductId,
 
+    // Catalog import
+    ReceiveCatalogImportConfirmation {
+        rows: Vec<CatalogRow>,
+    },
+
+    // Product gallery
+    ReceiveGalleryProductId,
+    ReceiveGalleryImage {
+        product_id: i64,
+    },
+
     // Cart
-    ReceiveRemoveCartItemId,
-    ReceiveEditCartItemQuantityId,
-    ReceiveEditCartItemQuantityAmount {
-        cart_item_id: i64,
+    ReceiveCouponCode,
+    ReceiveDeliveryLocation,
+
+    // Profile
+    ReceiveProfilePhone,
+    ReceiveAddressLabel,
+    ReceiveAddress {
+        label: String,
     },
 }
 
@@ -70,7 +113,7 @@ pub enum Command {
     Help,
 
     #[command(description = "Create a new account in the store.")]
-    Start,
+    Start(String),
 
     #[command(description = "Cancel the current dialogue.")]
     Cancel,
@@ -81,15 +124,69 @@ pub enum Command {
     #[command(description = "Add a new product.")]
     Add,
 
+    #[command(description = "Edit an existing product.")]
+    Edit,
+
     #[command(description = "Remove a product.")]
     Remove,
 
+    #[command(description = "Restore a removed product.")]
+    Restore,
+
+    #[command(description = "Manage a product's images.")]
+    Gallery,
+
+    #[command(
+        rename = "export_catalog",
+        description = "Export the catalog as CSV. Send a CSV back to import it."
+    )]
+    ExportCatalog,
+
+    #[command(
+        description = "Create or update a coupon, e.g. /coupon SUMMER10 10% min=20 expires=2026-08-31 uses=100 per_user=1. Lists coupons without arguments."
+    )]
+    Coupon(String),
+
+    #[command(
+        description = "Ban a user by id or @username, e.g. /ban @username spamming. Lists banned and restricted users without arguments."
+    )]
+    Ban(String),
+
+    #[command(
+        description = "Let a user browse but not order, e.g. /restrict @username chargebacks."
+    )]
+    Restrict(String),
+
+    #[command(description = "Lift a user's ban or restriction.")]
+    Unban(String),
+
+    #[command(description = "Mark an order as fulfilled, e.g. /fulfill 12.")]
+    Fulfill(String),
+
+    #[command(
+        rename = "cancel_order",
+        description = "Cancel or refund an order, e.g. /cancel_order 12."
+    )]
+    CancelOrder(String),
+
     #[command(description = "View your cart.")]
     Cart,
 
     #[command(description = "View your orders.")]
     Orders,
 
+    #[command(description = "Turn abandoned cart reminders on or off.")]
+    Reminders,
+
+    #[command(description = "Share your phone number and manage your delivery addresses.")]
+    Profile,
+
+    #[command(description = "View and manage your saved products.")]
+    Wishlist,
+
+    #[command(description = "View your loyalty points.")]
+    Points,
+
     #[command(description = "View the shop web app.")]
     Shop,
 }
@@ -101,14 +198,28 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
         .branch(
             case![State::Start]
                 .branch(case![Command::Help].endpoint(help))
-                .branch(case![Command::Start].endpoint(start)),
+                .branch(case![Command::Start(payload)].endpoint(start)),
         )
         .branch(case![Command::Cancel].endpoint(cancel))
         .branch(case![Command::Inventory].endpoint(inventory))
         .branch(case![Command::Add].endpoint(add_product))
+        .branch(case![Command::Edit].endpoint(edit_product))
         .branch(case![Command::Remove].endpoint(remove_product))
+        .branch(case![Command::Restore].endpoint(restore_product))
+        .branch(case![Command::Gallery].endpoint(gallery))
+        .branch(case![Command::ExportCatalog].endpoint(export_catalog))
+        .branch(case![Command::Coupon(args)].endpoint(coupon))
+        .branch(case![Command::Ban(args)].endpoint(ban))
+        .branch(case![Command::Restrict(args)].endpoint(restrict))
+        .branch(case![Command::Unban(args)].endpoint(unban))
+        .branch(case![Command::Fulfill(args)].endpoint(fulfill_order))
+        .branch(case![Command::CancelOrder(args)].endpoint(cancel_order))
         .branch(case!(Command::Cart).endpoint(view_cart))
         .branch(case!(Command::Orders).endpoint(view_orders))
+        .branch(case!(Command::Reminders).endpoint(reminders))
+        .branch(case!(Command::Profile).endpoint(profile))
+        .branch(case!(Command::Wishlist).endpoint(wishlist))
+        .branch(case!(Command::Points).endpoint(points))
         .branch(case!(Command::Shop).endpoint(shop));
 
     let message_handler = Update::filter_message()
@@ -134,20 +245,36 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
             }]
             .endpoint(receive_product_image),
         )
-        .branch(case![State::ReceiveRemoveCartItemId].endpoint(receive_remove_cart_item_id))
+        .branch(case![State::ReceiveEditProductId].endpoint(receive_edit_product_id))
         .branch(
-            case![State::ReceiveEditCartItemQuantityId]
-                .endpoint(receive_edit_cart_item_quantity_id),
+            case![State::ReceiveEditProductField { product_id, field }]
+                .endpoint(receive_edit_product_field),
         )
+        .branch(case![State::ReceiveGalleryProductId].endpoint(receive_gallery_product_id))
+        .branch(case![State::ReceiveGalleryImage { product_id }].endpoint(receive_gallery_image))
+        .branch(case![State::ReceiveCouponCode].endpoint(receive_coupon_code))
+        .branch(case![State::ReceiveDeliveryLocation].endpoint(receive_delivery_location))
+        .branch(case![State::ReceiveProfilePhone].endpoint(receive_profile_phone))
+        .branch(case![State::ReceiveAddressLabel].endpoint(receive_address_label))
+        .branch(case![State::ReceiveAddress { label }].endpoint(receive_address))
         .branch(
-            case![State::ReceiveEditCartItemQuantityAmount { cart_item_id }]
-                .endpoint(receive_edit_cart_item_quantity_amount),
+            case![State::Start]
+                .filter(|msg: Message| msg.document().is_some())
+                .endpoint(receive_catalog_document),
         )
         .branch(dptree::endpoint(invalid_state));
 
-    dialogue::enter::<Update, InMemStorage<State>, State, _>()
-        .branch(message_handler)
-        .branch(Update::filter_callback_query().endpoint(callback_query_handler))
+    // Banned users are dropped before they are registered. Inline queries carry no chat, so they
+    // are handled outside the dialogue.
+    dptree::entry()
+        .filter_async(is_not_banned)
+        .inspect_async(register_user)
+        .branch(Update::filter_inline_query().endpoint(inline_query))
+        .branch(
+            dialogue::enter::<Update, InMemStorage<State>, State, _>()
+                .branch(message_handler)
+                .branch(Update::filter_callback_query().endpoint(callback_query_handler)),
+        )
 }
 
 async fn callback_query_handler(
@@ -155,6 +282,7 @@ async fn callback_query_handler(
     dialogue: AppDialogue,
     q: CallbackQuery,
     pool: SqlitePool,
+    config: Config,
 ) -> HandlerResult {
     tracing::debug!("Callback query: {:#?}", q);
     if let Some(data) = &q.data {
@@ -162,23 +290,135 @@ async fn callback_query_handler(
 
         match data.split_whitespace().collect::<Vec<&str>>().as_slice() {
             ["view_product", product_id] => {
-                view_product_callback(bot, q.clone(), pool, product_id.parse::<i64>()?).await
+                view_product_callback(bot, q.clone(), pool, config, product_id.parse::<i64>()?)
+                    .await
+            }
+
+            ["product_image", product_id, index] => {
+                view_product_image_callback(
+                    bot,
+                    q.clone(),
+                    pool,
+                    config,
+                    product_id.parse::<i64>()?,
+                    index.parse::<usize>()?,
+                )
+                .await
+            }
+
+            ["gallery", action, product_id, index] => {
+                gallery_callback(
+                    bot,
+                    q.clone(),
+                    dialogue,
+                    pool,
+                    action,
+                    product_id.parse::<i64>()?,
+                    index.parse::<usize>()?,
+                )
+                .await
+            }
+
+            ["edit_product", field, product_id] => {
+                edit_product_callback(
+                    bot,
+                    q.clone(),
+                    dialogue,
+                    pool,
+                    config,
+                    field,
+                    product_id.parse::<i64>()?,
+                )
+                .await
+            }
+
+            ["restore_product", product_id] => {
+                restore_product_callback(bot, q.clone(), pool, product_id.parse::<i64>()?).await
+            }
+
+            ["import_catalog", action] => {
+                import_catalog_callback(bot, q.clone(), dialogue, pool, action).await
+            }
+
+            ["view_order", order_id] => {
+                view_order_callback(bot, q.clone(), pool, config, order_id.parse::<i64>()?).await
+            }
+
+            ["profile", action] => profile_callback(bot, q.clone(), dialogue, pool, action).await,
+
+            ["remove_address", address_id] => {
+                remove_address_callback(bot, q.clone(), pool, address_id.parse::<i64>()?).await
+            }
+
+            ["cart_address", address_id] => {
+                cart_address_callback(bot, q.clone(), pool, config, address_id.parse::<i64>()?)
+                    .await
+            }
+
+            ["split_bill", order_id] => {
+                split_bill_callback(bot, q.clone(), pool, config, order_id.parse::<i64>()?).await
             }
 
             ["add_to_cart", product_id] => {
-                add_to_cart_callback(bot, q.clone(), product_id.parse::<i64>()?, pool).await
+                add_to_cart_callback(bot, q.clone(), product_id.parse::<i64>()?, pool, config).await
+            }
+
+            ["save_product", product_id, index] => {
+                save_product_callback(
+                    bot,
+                    q.clone(),
+                    pool,
+                    product_id.parse::<i64>()?,
+                    index.parse::<usize>()?,
+                )
+                .await
+            }
+
+            ["wishlist_remove", product_id] => {
+                wishlist_remove_callback(bot, q.clone(), pool, config, product_id.parse::<i64>()?)
+                    .await
+            }
+
+            ["cart_item", action, cart_item_id] => {
+                cart_item_callback(
+                    bot,
+                    q.clone(),
+                    pool,
+                    config,
+                    action,
+                    cart_item_id.parse::<i64>()?,
+                )
+                .await
             }
 
-            ["remove_cart_item"] => remove_cart_item_callback(bot, q.clone(), dialogue).await,
+            ["apply_coupon"] => apply_coupon_callback(bot, q.clone(), dialogue).await,
+
+            ["remove_coupon"] => remove_coupon_callback(bot, q.clone(), pool, config).await,
 
-            ["edit_cart_item_quantity"] => {
-                edit_cart_item_quantity_callback(bot, q.clone(), dialogue).await
+            ["fulfillment_method", index] => {
+                fulfillment_method_callback(bot, q.clone(), pool, config, index.parse::<usize>()?)
+                    .await
             }
 
-            ["place_order"] => place_order_callback(bot, q.clone(), pool).await,
+            ["view_cart"] => view_cart_callback(bot, q.clone(), pool, config).await,
+
+            ["cart_reminders", setting] => {
+                cart_reminders_callback(bot, q.clone(), pool, *setting == "on").await
+            }
+
+            ["place_order"] => place_order_callback(bot, q.clone(), pool, config).await,
+
+            ["share_location"] => share_location_callback(bot, q.clone(), dialogue).await,
+
+            ["use_points"] => use_points_callback(bot, q.clone(), pool, config).await,
 
             ["back"] => back_callback(bot, q.clone()).await,
 
+            ["noop"] => {
+                bot.answer_callback_query(q.id.clone()).await?;
+                Ok(())
+            }
+
             _ => Ok(()),
         }
     } else {
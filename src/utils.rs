@@ -1,26 +1,149 @@
+use std::collections::HashMap;
 use std::path::Path;
 
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
 
-pub fn format_price(price: i64) -> String {
-    format!("${:.2}", price / 100)
+use crate::geo::{find_zone, DeliveryZone, Location};
+use crate::loyalty::LoyaltyConfig;
+use crate::money::Currency;
+use crate::pricing::{FulfillmentConfig, TaxConfig};
+use crate::promotions::Promotion;
+use crate::scheduler::{ReminderConfig, WishlistConfig};
+use teloxide::{prelude::*, types::InputFile};
+use url::Url;
+
+/// Products store a Telegram `file_id`, but rows seeded by hand may still hold a URL.
+pub fn product_photo(image: &str) -> InputFile {
+    match Url::parse(image) {
+        Ok(url) => InputFile::url(url),
+        Err(_) => InputFile::file_id(image),
+    }
+}
+
+/// Lets Telegram fetch and validate the image behind `url` once by sending it to `chat_id`,
+/// returning the `file_id` of the uploaded photo.
+pub async fn upload_image_url(bot: &Bot, chat_id: ChatId, url: &str) -> Option<String> {
+    let url = Url::parse(url.trim()).ok()?;
+
+    match bot.send_photo(chat_id, InputFile::url(url.clone())).await {
+        Ok(message) => message
+            .photo()
+            .and_then(|photos| photos.last())
+            .map(|photo| photo.file.id.clone()),
+        Err(err) => {
+            tracing::warn!("Failed to fetch image from {}: {}", url, err);
+            None
+        }
+    }
+}
+
+/// Deletes the command message that triggered a handler. In group chats the bot can only do so
+/// as an admin, so a failure is logged instead of aborting the command.
+pub async fn delete_command(bot: &Bot, msg: &Message) {
+    if let Err(err) = bot.delete_message(msg.chat.id, msg.id).await {
+        tracing::debug!("failed to delete command in chat {}: {}", msg.chat.id, err);
+    }
+}
+
+pub fn admin_id() -> eyre::Result<i64> {
+    Ok(std::env::var("ADMIN_ID")?.parse::<i64>()?)
 }
 
 pub fn assert_admin_id(id: i64) -> eyre::Result<()> {
-    let admin_id = std::env::var("ADMIN_ID")?.parse::<i64>()?;
-    if id != admin_id {
+    if id != admin_id()? {
         Err(eyre::eyre!("Only admin can perform this action"))
     } else {
         Ok(())
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Config {
-    open: String,
-    close: String,
-    payment_methods: Vec<String>,
-    fulfillment_methods: Vec<String>,
+    pub open: String,
+    pub close: String,
+    pub payment_methods: Vec<String>,
+    pub fulfillment_methods: Vec<String>,
+    /// Most units of all products together in one order.
+    #[serde(default)]
+    pub max_order_quantity: Option<i64>,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default)]
+    pub tax: TaxConfig,
+    #[serde(default)]
+    pub promotions: Vec<Promotion>,
+    /// Fees and limits per entry of `fulfillment_methods`.
+    #[serde(default)]
+    pub fulfillment: HashMap<String, FulfillmentConfig>,
+    #[serde(default)]
+    pub reminders: ReminderConfig,
+    #[serde(default)]
+    pub wishlist: WishlistConfig,
+    /// Where radius delivery zones are measured from. Zones are only checked if it is set.
+    #[serde(default)]
+    pub store_location: Option<Location>,
+    #[serde(default)]
+    pub delivery_zones: Vec<DeliveryZone>,
+    #[serde(default)]
+    pub loyalty: LoyaltyConfig,
+}
+
+impl Config {
+    /// The format for amounts recorded in `code`, falling back to a plain one for currencies
+    /// the store no longer uses.
+    pub fn currency_for(&self, code: &str) -> Currency {
+        if code == self.currency.code {
+            self.currency.clone()
+        } else {
+            Currency::plain(code)
+        }
+    }
+
+    /// The cart's chosen fulfillment method if the store still offers it, or the first one.
+    pub fn fulfillment_method(&self, chosen: Option<&str>) -> Option<&str> {
+        self.fulfillment_methods
+            .iter()
+            .find(|method| Some(method.as_str()) == chosen)
+            .or(self.fulfillment_methods.first())
+            .map(String::as_str)
+    }
+
+    /// The fees and limits for `method`, which are none if it has no `[fulfillment]` entry.
+    pub fn fulfillment_config(&self, method: Option<&str>) -> FulfillmentConfig {
+        method
+            .and_then(|method| self.fulfillment.get(method))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether methods that require an address only deliver within `delivery_zones`.
+    pub fn has_delivery_zones(&self) -> bool {
+        self.store_location.is_some() && !self.delivery_zones.is_empty()
+    }
+
+    /// The delivery zone containing `location`, if any.
+    pub fn delivery_zone(&self, location: Location) -> Option<&DeliveryZone> {
+        self.store_location
+            .and_then(|store| find_zone(&self.delivery_zones, store, location))
+    }
+
+    /// The `close` time, or `None` if it is not written as `HH:MM`.
+    pub fn closing_time(&self) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(&self.close, "%H:%M").ok()
+    }
+
+    /// Whether the store is open at `now`. Hours that can't be parsed count as always open.
+    pub fn is_open(&self, now: NaiveTime) -> bool {
+        let open = NaiveTime::parse_from_str(&self.open, "%H:%M").ok();
+
+        match (open, self.closing_time()) {
+            (Some(open), Some(close)) if open <= close => open <= now && now < close,
+            // Opening hours past midnight, such as 18:00 to 02:00.
+            (Some(open), Some(close)) => now >= open || now < close,
+            _ => true,
+        }
+    }
 }
 
 pub fn parse_config() -> eyre::Result<Config> {
@@ -41,4 +164,26 @@ mod tests {
 
         assert!(assert_admin_id(admin_id + 1).is_err());
     }
+
+    #[test]
+    fn test_is_open() {
+        let time = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        let config = |open: &str, close: &str| Config {
+            open: open.to_owned(),
+            close: close.to_owned(),
+            ..Config::default()
+        };
+
+        let day = config("06:00", "22:00");
+        assert!(day.is_open(time("06:00")));
+        assert!(!day.is_open(time("22:00")));
+        assert!(!day.is_open(time("03:00")));
+
+        let night = config("18:00", "02:00");
+        assert!(night.is_open(time("23:00")));
+        assert!(night.is_open(time("01:59")));
+        assert!(!night.is_open(time("12:00")));
+
+        assert!(config("", "").is_open(time("03:00")));
+    }
 }